@@ -0,0 +1,20 @@
+use std::{env, fs, io, process};
+
+use aoc_2022::cpu::{debugger::Debugger, Program};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: cpu_debugger <program file>");
+            process::exit(1);
+        }
+    };
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| panic!("could not read {path}: {e}"));
+    let program: Program = source.parse().unwrap_or_else(|e| panic!("{e}"));
+
+    let mut debugger = Debugger::new(program);
+    debugger
+        .repl(io::stdin().lock(), &mut io::stdout().lock())
+        .unwrap();
+}
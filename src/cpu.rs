@@ -1,75 +1,422 @@
-use std::{fmt::Display, str::FromStr};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use simple_grid::Grid;
 
+pub mod assembler;
+pub mod debugger;
+pub mod ocr;
+
+#[derive(Debug, Clone)]
 pub struct Cpu {
-    register: i64,
+    registers: [i64; Register::ALL.len()],
+    pc: usize,
+    cycle: usize,
+    // cycles already spent on the instruction at `pc`
+    progress: u32,
+    cycle_costs: CycleCosts,
 }
 
 impl Cpu {
     pub fn new() -> Self {
-        Self { register: 1 }
+        Self::with_cycle_costs(CycleCosts::default())
+    }
+
+    pub fn with_cycle_costs(cycle_costs: CycleCosts) -> Self {
+        let mut registers = [0; Register::ALL.len()];
+        registers[Register::X.index()] = 1;
+        Self {
+            registers,
+            pc: 0,
+            cycle: 0,
+            progress: 0,
+            cycle_costs,
+        }
+    }
+
+    pub fn register(&self, register: Register) -> i64 {
+        self.registers[register.index()]
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
     }
 
     pub fn run(&mut self, instructions: &[Instruction]) -> (Grid<char>, i64) {
-        let mut grid_data = vec!['.'; 40 * 6];
-        let mut get_instruction = false;
-        let mut instructions = instructions.iter();
-        let mut instruction_progress = 0;
-        let mut current_instruction = instructions.next().unwrap();
-        let mut sum_of_signal_strength = 0;
-        'run: for cycle in 1.. {
-            if get_instruction {
-                if let Some(instr) = instructions.next() {
-                    get_instruction = false;
-                    current_instruction = instr;
-                    instruction_progress = 0;
-                } else {
-                    break 'run;
-                }
+        self.run_program(&Program::new(instructions.to_vec()))
+    }
+
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    // starts from the first instruction at cycle 0 every time, but keeps the registers from any
+    // earlier run
+    pub fn run_program(&mut self, program: &Program) -> (Grid<char>, i64) {
+        self.pc = 0;
+        self.cycle = 0;
+        self.progress = 0;
+        let mut crt = Crt::default();
+        let mut sampler = SignalSampler::default();
+        self.run_with(program, &mut [&mut crt, &mut sampler]);
+        (crt.to_grid(), sampler.sum())
+    }
+
+    // runs until the program counter leaves the program, so a program that loops forever never returns
+    pub fn run_with(&mut self, program: &Program, devices: &mut [&mut dyn Device]) {
+        for state in self.cycles(program) {
+            for device in devices.iter_mut() {
+                device.on_cycle(&state);
             }
-            instruction_progress += 1;
+        }
+    }
+
+    pub fn cycles<'a>(&'a mut self, program: &'a Program) -> Cycles<'a> {
+        Cycles { cpu: self, program }
+    }
 
-            if self.crt_overlaps(cycle - 1) {
-                grid_data[cycle - 1] = '#';
+    fn tick(&mut self, program: &Program) -> Option<CycleState> {
+        let instruction = *program.get(self.pc)?;
+        self.cycle += 1;
+        self.progress += 1;
+        let register_during = self.register(Register::X);
+        if self.progress == self.cycle_costs.get(instruction.opcode()) {
+            self.execute(instruction);
+            self.progress = 0;
+        }
+        Some(CycleState {
+            cycle: self.cycle,
+            register_during,
+            register_after: self.register(Register::X),
+        })
+    }
+
+    // applied once the last cycle of the instruction has finished; arithmetic wraps around like a
+    // 64-bit register would rather than panicking
+    fn execute(&mut self, instruction: Instruction) {
+        let mut next_pc = self.pc + 1;
+        match instruction {
+            Instruction::Add(r, operand) => {
+                let value = self.operand_value(operand);
+                self.registers[r.index()] = self.register(r).wrapping_add(value);
+            }
+            Instruction::Sub(r, operand) => {
+                let value = self.operand_value(operand);
+                self.registers[r.index()] = self.register(r).wrapping_sub(value);
+            }
+            Instruction::Mul(r, operand) => {
+                let value = self.operand_value(operand);
+                self.registers[r.index()] = self.register(r).wrapping_mul(value);
+            }
+            Instruction::Load(r, v) => self.registers[r.index()] = v,
+            Instruction::Jump(target) => next_pc = target,
+            Instruction::JumpIfZero(r, target) => {
+                if self.register(r) == 0 {
+                    next_pc = target;
+                }
+            }
+            Instruction::JumpIfNotZero(r, target) => {
+                if self.register(r) != 0 {
+                    next_pc = target;
+                }
             }
+            Instruction::NoOp => (),
+        }
+        self.pc = next_pc;
+    }
+
+    fn operand_value(&self, operand: Operand) -> i64 {
+        match operand {
+            Operand::Register(r) => self.register(r),
+            Operand::Constant(v) => v,
+        }
+    }
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Cycles<'a> {
+    cpu: &'a mut Cpu,
+    program: &'a Program,
+}
+
+impl Iterator for Cycles<'_> {
+    type Item = CycleState;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cpu.tick(self.program)
+    }
+}
+
+// `register_during` is the x register while the cycle is running, `register_after` includes
+// the effect of an instruction that finished on this cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleState {
+    cycle: usize,
+    register_during: i64,
+    register_after: i64,
+}
+
+impl CycleState {
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn register_during(&self) -> i64 {
+        self.register_during
+    }
+
+    pub fn register_after(&self) -> i64 {
+        self.register_after
+    }
+
+    pub fn signal_strength(&self) -> i64 {
+        self.cycle as i64 * self.register_during
+    }
+}
+
+pub trait Device {
+    fn on_cycle(&mut self, state: &CycleState);
+}
+
+#[derive(Debug, Clone)]
+pub struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    pixels: Vec<bool>,
+}
+
+impl Crt {
+    pub fn new(width: usize, height: usize, sprite_width: usize) -> Self {
+        Self {
+            width,
+            height,
+            sprite_width,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn pixels(&self) -> Grid<bool> {
+        Grid::new(self.width, self.height, self.pixels.clone())
+    }
+
+    pub fn to_grid(&self) -> Grid<char> {
+        Grid::new(
+            self.width,
+            self.height,
+            self.pixels
+                .iter()
+                .map(|lit| if *lit { '#' } else { '.' })
+                .collect(),
+        )
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        for row in self.pixels.chunks(self.width) {
+            s.extend(row.iter().map(|lit| if *lit { '#' } else { '.' }));
+            s.push('\n');
+        }
+        s
+    }
+
+    // the sprite is centered on x, with any extra width going to the right
+    fn sprite_covers(&self, x: i64, column: i64) -> bool {
+        let left = x - (self.sprite_width as i64 - 1) / 2;
+        (left..left + self.sprite_width as i64).contains(&column)
+    }
+}
+
+impl Default for Crt {
+    fn default() -> Self {
+        Self::new(40, 6, 3)
+    }
+}
+
+impl Device for Crt {
+    fn on_cycle(&mut self, state: &CycleState) {
+        let position = state.cycle - 1;
+        if position >= self.pixels.len() {
+            return;
+        }
+        let column = (position % self.width) as i64;
+        if self.sprite_covers(state.register_during, column) {
+            self.pixels[position] = true;
+        }
+    }
+}
 
-            if Self::is_interesting_cycle(cycle) {
-                let signal_strength = cycle as i64 * self.register;
-                sum_of_signal_strength += signal_strength;
+#[derive(Debug, Clone)]
+enum SampleCycles {
+    Every { first: usize, interval: usize },
+    At(Vec<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SignalSampler {
+    cycles: SampleCycles,
+    samples: Vec<(usize, i64)>,
+}
+
+impl SignalSampler {
+    pub fn new(first: usize, interval: usize) -> Self {
+        if interval == 0 {
+            panic!("interval must be positive")
+        }
+        Self {
+            cycles: SampleCycles::Every { first, interval },
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn at(cycles: &[usize]) -> Self {
+        Self {
+            cycles: SampleCycles::At(cycles.to_vec()),
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn samples(&self) -> &[(usize, i64)] {
+        &self.samples
+    }
+
+    pub fn sum(&self) -> i64 {
+        self.samples.iter().map(|(_, strength)| strength).sum()
+    }
+
+    fn is_sampled(&self, cycle: usize) -> bool {
+        match &self.cycles {
+            SampleCycles::Every { first, interval } => {
+                cycle >= *first && (cycle - first).is_multiple_of(*interval)
             }
+            SampleCycles::At(cycles) => cycles.contains(&cycle),
+        }
+    }
+}
 
-            if instruction_progress == current_instruction.cycle_length() {
-                match current_instruction {
-                    Instruction::Add(v) => self.register += v,
-                    Instruction::NoOp => (),
-                }
-                get_instruction = true;
+// the puzzle samples cycle 20 and every 40th cycle after that
+impl Default for SignalSampler {
+    fn default() -> Self {
+        Self::new(20, 40)
+    }
+}
+
+impl Device for SignalSampler {
+    fn on_cycle(&mut self, state: &CycleState) {
+        if self.is_sampled(state.cycle) {
+            self.samples.push((state.cycle, state.signal_strength()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    X,
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Register {
+    pub const ALL: [Register; 5] = [
+        Register::X,
+        Register::A,
+        Register::B,
+        Register::C,
+        Register::D,
+    ];
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Register::X => "x",
+                Register::A => "a",
+                Register::B => "b",
+                Register::C => "c",
+                Register::D => "d",
             }
+        )
+    }
+}
+
+impl FromStr for Register {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x" => Ok(Self::X),
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            "c" => Ok(Self::C),
+            "d" => Ok(Self::D),
+            _e => Err(()),
         }
-        (Grid::new(40, 6, grid_data), sum_of_signal_strength)
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Constant(i64),
+}
 
-    fn is_interesting_cycle(cycle: usize) -> bool {
-        cycle >= 20 && (cycle - 20) % 40 == 0
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::Register(r) => write!(f, "{}", r),
+            Operand::Constant(v) => write!(f, "{}", v),
+        }
     }
+}
+
+impl FromStr for Operand {
+    type Err = ();
 
-    fn crt_overlaps(&self, position: usize) -> bool {
-        (self.register - 1..=self.register + 1).contains(&(position as i64 % 40))
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(r) => Ok(Self::Register(r)),
+            Err(()) => Ok(Self::Constant(s.parse().map_err(|_| ())?)),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// jump targets are instruction indices, `Program` resolves labels into them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
-    Add(i64),
+    Add(Register, Operand),
+    Sub(Register, Operand),
+    Mul(Register, Operand),
+    Load(Register, i64),
+    Jump(usize),
+    JumpIfZero(Register, usize),
+    JumpIfNotZero(Register, usize),
     NoOp,
 }
 
 impl Instruction {
-    fn cycle_length(&self) -> u32 {
+    pub fn opcode(&self) -> Opcode {
         match self {
-            Instruction::Add(_) => 2,
-            Instruction::NoOp => 1,
+            Instruction::Add(_, _) => Opcode::Add,
+            Instruction::Sub(_, _) => Opcode::Sub,
+            Instruction::Mul(_, _) => Opcode::Mul,
+            Instruction::Load(_, _) => Opcode::Load,
+            Instruction::Jump(_) => Opcode::Jump,
+            Instruction::JumpIfZero(_, _) => Opcode::JumpIfZero,
+            Instruction::JumpIfNotZero(_, _) => Opcode::JumpIfNotZero,
+            Instruction::NoOp => Opcode::NoOp,
         }
     }
 }
@@ -77,7 +424,14 @@ impl Instruction {
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Instruction::Add(v) => write!(f, "addx {}", v),
+            Instruction::Add(Register::X, Operand::Constant(v)) => write!(f, "addx {}", v),
+            Instruction::Add(r, operand) => write!(f, "add {} {}", r, operand),
+            Instruction::Sub(r, operand) => write!(f, "sub {} {}", r, operand),
+            Instruction::Mul(r, operand) => write!(f, "mul {} {}", r, operand),
+            Instruction::Load(r, v) => write!(f, "load {} {}", r, v),
+            Instruction::Jump(target) => write!(f, "jmp {}", target),
+            Instruction::JumpIfZero(r, target) => write!(f, "jz {} {}", r, target),
+            Instruction::JumpIfNotZero(r, target) => write!(f, "jnz {} {}", r, target),
             Instruction::NoOp => write!(f, "noop"),
         }
     }
@@ -87,11 +441,290 @@ impl FromStr for Instruction {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<_> = s.split(' ').collect();
-        match parts[0] {
-            "addx" => Ok(Self::Add(parts[1].parse().map_err(|_| ())?)),
-            "noop" => Ok(Self::NoOp),
-            _e => Err(()),
+        let parts: Vec<_> = s.split_whitespace().collect();
+        let arg = |i: usize| parts.get(i).copied().ok_or(());
+        let instruction = match parts.first().copied().ok_or(())? {
+            // the original two instructions, which only know about the x register
+            "addx" => Self::Add(
+                Register::X,
+                Operand::Constant(arg(1)?.parse().map_err(|_| ())?),
+            ),
+            "noop" => Self::NoOp,
+            "add" => Self::Add(arg(1)?.parse()?, arg(2)?.parse()?),
+            "sub" => Self::Sub(arg(1)?.parse()?, arg(2)?.parse()?),
+            "mul" => Self::Mul(arg(1)?.parse()?, arg(2)?.parse()?),
+            "load" => Self::Load(arg(1)?.parse()?, arg(2)?.parse().map_err(|_| ())?),
+            "jmp" => Self::Jump(arg(1)?.parse().map_err(|_| ())?),
+            "jz" => Self::JumpIfZero(arg(1)?.parse()?, arg(2)?.parse().map_err(|_| ())?),
+            "jnz" => Self::JumpIfNotZero(arg(1)?.parse()?, arg(2)?.parse().map_err(|_| ())?),
+            _e => return Err(()),
+        };
+        let arg_count = match parts[0] {
+            "noop" => 0,
+            "addx" | "jmp" => 1,
+            _ => 2,
+        };
+        if parts.len() != arg_count + 1 {
+            return Err(());
         }
+        Ok(instruction)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+    Add,
+    Sub,
+    Mul,
+    Load,
+    Jump,
+    JumpIfZero,
+    JumpIfNotZero,
+    NoOp,
+}
+
+impl Opcode {
+    pub const ALL: [Opcode; 8] = [
+        Opcode::Add,
+        Opcode::Sub,
+        Opcode::Mul,
+        Opcode::Load,
+        Opcode::Jump,
+        Opcode::JumpIfZero,
+        Opcode::JumpIfNotZero,
+        Opcode::NoOp,
+    ];
+}
+
+// `addx` taking two cycles and `noop` taking one are what the puzzle is built on
+const DEFAULT_CYCLE_COSTS: [(Opcode, u32); 8] = [
+    (Opcode::Add, 2),
+    (Opcode::Sub, 2),
+    (Opcode::Mul, 3),
+    (Opcode::Load, 1),
+    (Opcode::Jump, 1),
+    (Opcode::JumpIfZero, 1),
+    (Opcode::JumpIfNotZero, 1),
+    (Opcode::NoOp, 1),
+];
+
+#[derive(Debug, Clone)]
+pub struct CycleCosts {
+    costs: HashMap<Opcode, u32>,
+}
+
+impl CycleCosts {
+    pub fn get(&self, opcode: Opcode) -> u32 {
+        self.costs[&opcode]
+    }
+
+    pub fn set(&mut self, opcode: Opcode, cycles: u32) {
+        if cycles == 0 {
+            panic!("an instruction needs at least one cycle")
+        }
+        self.costs.insert(opcode, cycles);
+    }
+}
+
+impl Default for CycleCosts {
+    fn default() -> Self {
+        Self {
+            costs: DEFAULT_CYCLE_COSTS.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    labels: HashMap<String, usize>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Self {
+            instructions,
+            labels: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, pc: usize) -> Option<&Instruction> {
+        self.instructions.get(pc)
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn label(&self, name: &str) -> Option<usize> {
+        self.labels.get(name).copied()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        assembler::encode(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        assembler::decode(bytes)
+    }
+}
+
+impl FromStr for Program {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        assembler::assemble(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compatibility_test() {
+        let instructions: Vec<Instruction> = ["noop", "addx 3", "addx -5"]
+            .iter()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        assert_eq!(instructions[1].to_string(), "addx 3");
+
+        let mut cpu = Cpu::new();
+        cpu.run(&instructions);
+        assert_eq!(cpu.register(Register::X), -1);
+    }
+
+    #[test]
+    fn empty_program_test() {
+        let mut cpu = Cpu::new();
+        let (grid, signal) = cpu.run(&[]);
+
+        assert_eq!(signal, 0);
+        assert!(!grid.contains(&'#'));
+    }
+
+    #[test]
+    fn program_with_labels_test() {
+        // a = 5!, counting b down to zero
+        let program: Program = "load a 1
+load b 5
+loop:
+mul a b
+sub b 1
+jnz b loop
+add x a"
+            .parse()
+            .unwrap();
+        assert_eq!(program.label("loop"), Some(2));
+        assert_eq!(
+            program.get(4),
+            Some(&Instruction::JumpIfNotZero(Register::B, 2))
+        );
+
+        let mut cpu = Cpu::new();
+        cpu.run_program(&program);
+        assert_eq!(cpu.register(Register::A), 120);
+        assert_eq!(cpu.register(Register::X), 121);
+        assert_eq!(cpu.pc(), 6);
+    }
+
+    #[test]
+    fn rerun_test() {
+        let program: Program = "load x 1\nnoop\naddx 3\naddx -5".parse().unwrap();
+        let mut cpu = Cpu::new();
+        let first = cpu.run_program(&program);
+        let second = cpu.run_program(&program);
+
+        assert_eq!(second, first);
+        assert_eq!(cpu.cycle(), 6);
+        assert_eq!(cpu.register(Register::X), -1);
+
+        // registers carry over, so without the load x starts where it ended
+        cpu.run(&[Instruction::NoOp]);
+        assert_eq!((cpu.cycle(), cpu.register(Register::X)), (1, -1));
+    }
+
+    #[test]
+    fn overflow_test() {
+        let program: Program = "load a 9223372036854775807
+add a 1
+load b -9223372036854775808
+sub b 1
+load c 3
+load d 10
+loop:
+mul c c
+sub d 1
+jnz d loop"
+            .parse()
+            .unwrap();
+
+        let mut cpu = Cpu::new();
+        cpu.run_program(&program);
+        assert_eq!(cpu.register(Register::A), i64::MIN);
+        assert_eq!(cpu.register(Register::B), i64::MAX);
+        assert_eq!(
+            cpu.register(Register::C),
+            (0..10).fold(3i64, |c, _| c.wrapping_mul(c))
+        );
+    }
+
+    #[test]
+    fn cycle_costs_test() {
+        let mut costs = CycleCosts::default();
+        assert_eq!(costs.get(Opcode::Add), 2);
+        costs.set(Opcode::NoOp, 20);
+
+        // with the cost raised, the first sampled cycle (20) happens during the noop
+        let mut cpu = Cpu::with_cycle_costs(costs);
+        let (_, signal) = cpu.run(&[Instruction::NoOp]);
+        assert_eq!(signal, 20);
+    }
+
+    #[test]
+    fn cycles_test() {
+        let program = Program::new(vec![
+            Instruction::NoOp,
+            Instruction::Add(Register::X, Operand::Constant(3)),
+            Instruction::Add(Register::X, Operand::Constant(-5)),
+        ]);
+        let mut cpu = Cpu::new();
+        let states: Vec<_> = cpu
+            .cycles(&program)
+            .map(|s| (s.cycle(), s.register_during(), s.register_after()))
+            .collect();
+
+        assert_eq!(
+            states,
+            vec![(1, 1, 1), (2, 1, 1), (3, 1, 4), (4, 4, 4), (5, 4, -1)]
+        );
+    }
+
+    #[test]
+    fn devices_test() {
+        let program = Program::new(vec![
+            Instruction::Add(Register::X, Operand::Constant(2)),
+            Instruction::NoOp,
+            Instruction::NoOp,
+        ]);
+        let mut crt = Crt::new(2, 2, 1);
+        let mut sampler = SignalSampler::at(&[1, 3]);
+        Cpu::new().run_with(&program, &mut [&mut crt, &mut sampler]);
+
+        // x is 1 for the first two cycles, so only column 1 is lit, then it moves off screen
+        assert_eq!(
+            crt.pixels(),
+            Grid::new(2, 2, vec![false, true, false, false])
+        );
+        assert_eq!(sampler.samples(), &[(1, 1), (3, 9)]);
+        assert_eq!(sampler.sum(), 10);
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        assert!("addx".parse::<Instruction>().is_err());
+        assert!("add y 1".parse::<Instruction>().is_err());
+        assert!("noop 1".parse::<Instruction>().is_err());
+        assert!("jmp nowhere".parse::<Program>().is_err());
     }
 }
@@ -0,0 +1,369 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{Instruction, Opcode, Operand, Program, Register};
+
+const MAGIC: &[u8; 4] = b"CPU1";
+
+const MNEMONICS: [&str; 9] = [
+    "addx", "add", "sub", "mul", "load", "jmp", "jz", "jnz", "noop",
+];
+
+// One instruction per line. `;` starts a comment, `name:` labels the next instruction (on its own
+// line or in front of one), and jumps take either a label or an instruction index.
+pub fn assemble(source: &str) -> Result<Program, String> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let mut line = line.split(';').next().unwrap().trim();
+        while let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_valid_label(label) {
+                return Err(format!(
+                    "invalid label '{}' on line {}",
+                    label,
+                    line_idx + 1
+                ));
+            }
+            if labels.insert(label.to_owned(), lines.len()).is_some() {
+                return Err(format!(
+                    "duplicate label '{}' on line {}",
+                    label,
+                    line_idx + 1
+                ));
+            }
+            line = rest.trim();
+        }
+        if !line.is_empty() {
+            lines.push((line_idx, line));
+        }
+    }
+
+    let mut instructions = Vec::new();
+    for (line_idx, line) in lines {
+        let mut parts: Vec<_> = line.split_whitespace().map(|p| p.to_owned()).collect();
+        if matches!(parts[0].as_str(), "jmp" | "jz" | "jnz") {
+            let target = parts.last_mut().unwrap();
+            if let Some(pc) = labels.get(target.as_str()) {
+                *target = pc.to_string();
+            }
+        }
+        let instruction = parts
+            .join(" ")
+            .parse()
+            .map_err(|_| format!("invalid instruction '{}' on line {}", line, line_idx + 1))?;
+        instructions.push(instruction);
+    }
+
+    Ok(Program {
+        instructions,
+        labels,
+    })
+}
+
+// a label must not read as anything else a jump could take or the disassembler could write
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && !label.contains(|c: char| c.is_whitespace() || c == ':' || c == ';')
+        && label.parse::<usize>().is_err()
+        && label.parse::<Register>().is_err()
+        && !MNEMONICS.contains(&label)
+}
+
+// labels are written back in front of the instruction they point at, and jumps to a labelled
+// index use the label name
+pub fn disassemble(program: &Program) -> String {
+    let mut labels_at: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for (name, pc) in &program.labels {
+        labels_at.entry(*pc).or_default().push(name);
+    }
+    for names in labels_at.values_mut() {
+        names.sort();
+    }
+
+    let mut s = String::new();
+    for pc in 0..=program.instructions.len() {
+        for name in labels_at.get(&pc).into_iter().flatten() {
+            s.push_str(&format!("{}:\n", name));
+        }
+        let instruction = match program.instructions.get(pc) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        let target_label = |target: &usize| labels_at.get(target).map(|names| names[0]);
+        let line = match instruction {
+            Instruction::Jump(target) => target_label(target).map(|l| format!("jmp {}", l)),
+            Instruction::JumpIfZero(r, target) => {
+                target_label(target).map(|l| format!("jz {} {}", r, l))
+            }
+            Instruction::JumpIfNotZero(r, target) => {
+                target_label(target).map(|l| format!("jnz {} {}", r, l))
+            }
+            _ => None,
+        };
+        s.push_str(&line.unwrap_or_else(|| instruction.to_string()));
+        s.push('\n');
+    }
+    s
+}
+
+// Layout: the magic bytes, the instruction count, each instruction as an opcode byte followed by
+// its arguments, then the label count and each label as its name and target. Every number is a
+// LEB128 varint, signed ones zigzag encoded first, so small programs stay small.
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    write_unsigned(&mut bytes, program.instructions.len() as u64);
+    for instruction in &program.instructions {
+        bytes.push(opcode_byte(instruction.opcode()));
+        match instruction {
+            Instruction::Add(r, operand)
+            | Instruction::Sub(r, operand)
+            | Instruction::Mul(r, operand) => {
+                bytes.push(r.index() as u8);
+                match operand {
+                    Operand::Register(other) => {
+                        bytes.push(0);
+                        bytes.push(other.index() as u8);
+                    }
+                    Operand::Constant(v) => {
+                        bytes.push(1);
+                        write_signed(&mut bytes, *v);
+                    }
+                }
+            }
+            Instruction::Load(r, v) => {
+                bytes.push(r.index() as u8);
+                write_signed(&mut bytes, *v);
+            }
+            Instruction::Jump(target) => write_unsigned(&mut bytes, *target as u64),
+            Instruction::JumpIfZero(r, target) | Instruction::JumpIfNotZero(r, target) => {
+                bytes.push(r.index() as u8);
+                write_unsigned(&mut bytes, *target as u64);
+            }
+            Instruction::NoOp => (),
+        }
+    }
+
+    // sorted so that equal programs always encode to the same bytes
+    let labels: BTreeMap<_, _> = program.labels.iter().collect();
+    write_unsigned(&mut bytes, labels.len() as u64);
+    for (name, pc) in labels {
+        write_unsigned(&mut bytes, name.len() as u64);
+        bytes.extend_from_slice(name.as_bytes());
+        write_unsigned(&mut bytes, *pc as u64);
+    }
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Program, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not an encoded program".to_string());
+    }
+
+    let count = reader.unsigned()?;
+    let mut instructions = Vec::new();
+    for _ in 0..count {
+        let opcode = reader.byte()?;
+        let opcode = *Opcode::ALL
+            .get(opcode as usize)
+            .ok_or_else(|| format!("unknown opcode {} at byte {}", opcode, reader.pos - 1))?;
+        let instruction = match opcode {
+            Opcode::Add | Opcode::Sub | Opcode::Mul => {
+                let r = reader.register()?;
+                let operand = match reader.byte()? {
+                    0 => Operand::Register(reader.register()?),
+                    1 => Operand::Constant(reader.signed()?),
+                    tag => return Err(format!("unknown operand tag {}", tag)),
+                };
+                match opcode {
+                    Opcode::Add => Instruction::Add(r, operand),
+                    Opcode::Sub => Instruction::Sub(r, operand),
+                    _ => Instruction::Mul(r, operand),
+                }
+            }
+            Opcode::Load => Instruction::Load(reader.register()?, reader.signed()?),
+            Opcode::Jump => Instruction::Jump(reader.unsigned()? as usize),
+            Opcode::JumpIfZero => {
+                Instruction::JumpIfZero(reader.register()?, reader.unsigned()? as usize)
+            }
+            Opcode::JumpIfNotZero => {
+                Instruction::JumpIfNotZero(reader.register()?, reader.unsigned()? as usize)
+            }
+            Opcode::NoOp => Instruction::NoOp,
+        };
+        instructions.push(instruction);
+    }
+
+    let mut labels = HashMap::new();
+    for _ in 0..reader.unsigned()? {
+        let len = reader.unsigned()? as usize;
+        let name = String::from_utf8(reader.take(len)?.to_vec())
+            .map_err(|_| "label is not valid utf-8".to_string())?;
+        if !is_valid_label(&name) {
+            return Err(format!("invalid label '{}'", name));
+        }
+        labels.insert(name, reader.unsigned()? as usize);
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(format!("{} trailing bytes", bytes.len() - reader.pos));
+    }
+    Ok(Program {
+        instructions,
+        labels,
+    })
+}
+
+fn opcode_byte(opcode: Opcode) -> u8 {
+    Opcode::ALL.iter().position(|o| *o == opcode).unwrap() as u8
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let low = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            bytes.push(low);
+            return;
+        }
+        bytes.push(low | 0x80);
+    }
+}
+
+fn write_signed(bytes: &mut Vec<u8>, v: i64) {
+    write_unsigned(bytes, ((v << 1) ^ (v >> 63)) as u64);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err("unexpected end of input".to_string());
+        }
+        let taken = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn register(&mut self) -> Result<Register, String> {
+        let r = self.byte()?;
+        Register::ALL
+            .get(r as usize)
+            .copied()
+            .ok_or_else(|| format!("unknown register {}", r))
+    }
+
+    fn unsigned(&mut self) -> Result<u64, String> {
+        let mut v = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            v |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err("varint is too long".to_string())
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let v = self.unsigned()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "; computes 5! into a
+load a 1
+load b 5 ; counter
+loop: mul a b
+sub b 1
+jnz b loop
+add x a
+addx -3
+noop
+end:";
+
+    #[test]
+    fn assemble_test() {
+        let program = assemble(SOURCE).unwrap();
+
+        assert_eq!(program.instructions().len(), 8);
+        assert_eq!(program.label("loop"), Some(2));
+        assert_eq!(program.label("end"), Some(8));
+        assert_eq!(
+            program.get(4),
+            Some(&Instruction::JumpIfNotZero(Register::B, 2))
+        );
+    }
+
+    #[test]
+    fn disassemble_round_trip() {
+        let program = assemble(SOURCE).unwrap();
+        let text = disassemble(&program);
+
+        assert_eq!(
+            text,
+            "load a 1\nload b 5\nloop:\nmul a b\nsub b 1\njnz b loop\nadd x a\naddx -3\nnoop\nend:\n"
+        );
+        assert_eq!(assemble(&text).unwrap(), program);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let program = assemble(SOURCE).unwrap();
+        let bytes = program.to_bytes();
+
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(Program::from_bytes(&bytes).unwrap(), program);
+        assert_eq!(assemble(&disassemble(&program)).unwrap().to_bytes(), bytes);
+    }
+
+    #[test]
+    fn varint_test() {
+        for v in [0, 1, -1, 63, -64, 64, i64::MAX, i64::MIN] {
+            let mut bytes = Vec::new();
+            write_signed(&mut bytes, v);
+            let mut reader = Reader {
+                bytes: &bytes,
+                pos: 0,
+            };
+            assert_eq!(reader.signed(), Ok(v));
+        }
+    }
+
+    #[test]
+    fn decode_errors_test() {
+        assert!(decode(b"nope").is_err());
+        let mut bytes = assemble("noop").unwrap().to_bytes();
+        bytes.push(0);
+        assert!(decode(&bytes).is_err());
+        assert!(decode(&bytes[..5]).is_err());
+
+        let mut bytes = assemble("noop").unwrap().to_bytes();
+        *bytes.last_mut().unwrap() = 1;
+        bytes.extend([1, b'3', 0]);
+        assert_eq!(decode(&bytes), Err("invalid label '3'".to_string()));
+    }
+
+    #[test]
+    fn invalid_labels_test() {
+        for label in ["3", "x", "a", "jmp", "noop", "addx"] {
+            assert_eq!(
+                assemble(&format!("noop\n{}: noop\njmp 0", label)),
+                Err(format!("invalid label '{}' on line 2", label))
+            );
+        }
+        assert!(assemble("x1: noop\njmp x1").is_ok());
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::{self, BufRead, Write},
+};
+
+use super::{Cpu, Crt, CycleState, Device, Instruction, Program, Register};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    // stops once this cycle has run
+    Cycle(usize),
+    // stops as soon as a register holds the value
+    Register(Register, i64),
+    // stops before the first cycle of the instruction at this index
+    Pc(usize),
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Cycle(cycle) => write!(f, "cycle {}", cycle),
+            Breakpoint::Register(r, v) => write!(f, "{} {}", r, v),
+            Breakpoint::Pc(pc) => write!(f, "pc {}", pc),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    Stepped,
+    Breakpoint(Breakpoint),
+    Halted,
+    // `resume` ran for its maximum number of cycles without stopping otherwise
+    CycleLimit,
+}
+
+// what a single cycle changed, enough to undo it
+#[derive(Debug, Clone, Copy)]
+struct Undo {
+    registers: [i64; Register::ALL.len()],
+    pc: usize,
+    cycle: usize,
+    progress: u32,
+    // the pixel the cycle lit, if it wasn't lit already
+    lit_pixel: Option<usize>,
+}
+
+pub struct Debugger {
+    program: Program,
+    cpu: Cpu,
+    crt: Crt,
+    // one entry per cycle that has run, newest last, dropping the oldest past `history_depth`
+    history: VecDeque<Undo>,
+    history_depth: usize,
+    max_resume_cycles: usize,
+    breakpoints: Vec<Breakpoint>,
+}
+
+impl Debugger {
+    pub fn new(program: Program) -> Self {
+        Self::with_cpu(Cpu::new(), program)
+    }
+
+    pub fn with_cpu(cpu: Cpu, program: Program) -> Self {
+        Self {
+            program,
+            cpu,
+            crt: Crt::default(),
+            history: VecDeque::new(),
+            history_depth: 100_000,
+            max_resume_cycles: 1_000_000,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    // how many cycles can be rewound
+    pub fn history_depth(&self) -> usize {
+        self.history_depth
+    }
+
+    pub fn set_history_depth(&mut self, depth: usize) {
+        self.history_depth = depth;
+        let excess = self.history.len().saturating_sub(depth);
+        self.history.drain(..excess);
+    }
+
+    pub fn max_resume_cycles(&self) -> usize {
+        self.max_resume_cycles
+    }
+
+    // stops programs that never hit a breakpoint or halt, like `loop: jmp loop`
+    pub fn set_max_resume_cycles(&mut self, cycles: usize) {
+        self.max_resume_cycles = cycles;
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn crt(&self) -> &Crt {
+        &self.crt
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.program.get(self.cpu.pc).is_none()
+    }
+
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.program.get(self.cpu.pc).copied()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| *b != breakpoint);
+        self.breakpoints.len() != len
+    }
+
+    pub fn step_cycle(&mut self) -> Option<CycleState> {
+        if self.is_halted() {
+            return None;
+        }
+        let mut undo = Undo {
+            registers: self.cpu.registers,
+            pc: self.cpu.pc,
+            cycle: self.cpu.cycle,
+            progress: self.cpu.progress,
+            lit_pixel: None,
+        };
+        let state = self.cpu.tick(&self.program)?;
+        let position = state.cycle - 1;
+        let was_lit = self.crt.pixels.get(position).copied();
+        self.crt.on_cycle(&state);
+        if was_lit == Some(false) && self.crt.pixels[position] {
+            undo.lit_pixel = Some(position);
+        }
+
+        if self.history_depth > 0 {
+            if self.history.len() == self.history_depth {
+                self.history.pop_front();
+            }
+            self.history.push_back(undo);
+        }
+        Some(state)
+    }
+
+    // runs the remaining cycles of the current instruction
+    pub fn step_instruction(&mut self) -> StopReason {
+        loop {
+            if self.step_cycle().is_none() {
+                return StopReason::Halted;
+            }
+            if self.cpu.progress == 0 {
+                return StopReason::Stepped;
+            }
+        }
+    }
+
+    // always runs at least one cycle, so continuing from a breakpoint does not stop on it again
+    pub fn resume(&mut self) -> StopReason {
+        for _ in 0..self.max_resume_cycles {
+            let state = match self.step_cycle() {
+                Some(state) => state,
+                None => return StopReason::Halted,
+            };
+            if let Some(breakpoint) = self.hit_breakpoint(&state) {
+                return StopReason::Breakpoint(breakpoint);
+            }
+        }
+        StopReason::CycleLimit
+    }
+
+    // returns how many cycles were actually undone, which is limited by the history depth
+    pub fn rewind(&mut self, cycles: usize) -> usize {
+        let mut rewound = 0;
+        while rewound < cycles {
+            match self.history.pop_back() {
+                Some(undo) => {
+                    self.cpu.registers = undo.registers;
+                    self.cpu.pc = undo.pc;
+                    self.cpu.cycle = undo.cycle;
+                    self.cpu.progress = undo.progress;
+                    if let Some(position) = undo.lit_pixel {
+                        self.crt.pixels[position] = false;
+                    }
+                    rewound += 1;
+                }
+                None => break,
+            }
+        }
+        rewound
+    }
+
+    fn hit_breakpoint(&self, state: &CycleState) -> Option<Breakpoint> {
+        self.breakpoints.iter().copied().find(|b| match b {
+            Breakpoint::Cycle(cycle) => state.cycle == *cycle,
+            Breakpoint::Register(r, v) => self.cpu.register(*r) == *v,
+            Breakpoint::Pc(pc) => self.cpu.pc == *pc && self.cpu.progress == 0,
+        })
+    }
+
+    pub fn status(&self) -> String {
+        let registers: Vec<_> = Register::ALL
+            .iter()
+            .map(|r| format!("{}={}", r, self.cpu.register(*r)))
+            .collect();
+        let next = match self.current_instruction() {
+            Some(instruction) => instruction.to_string(),
+            None => "halted".to_owned(),
+        };
+        format!(
+            "cycle {} pc {} {} | next: {}",
+            self.cpu.cycle,
+            self.cpu.pc,
+            registers.join(" "),
+            next
+        )
+    }
+
+    // reads commands line by line until `quit` or the end of the input
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, output: &mut W) -> io::Result<()> {
+        writeln!(output, "{}", self.status())?;
+        for line in input.lines() {
+            let line = line?;
+            let parts: Vec<_> = line.split_whitespace().collect();
+            let count = |i: usize| parts.get(i).and_then(|p| p.parse().ok()).unwrap_or(1);
+            match parts.first().copied() {
+                Some("s" | "step") => {
+                    for _ in 0..count(1) {
+                        if self.step_cycle().is_none() {
+                            break;
+                        }
+                    }
+                }
+                Some("n" | "next") => {
+                    for _ in 0..count(1) {
+                        if self.step_instruction() == StopReason::Halted {
+                            break;
+                        }
+                    }
+                }
+                Some("c" | "continue") => match self.resume() {
+                    StopReason::Breakpoint(b) => writeln!(output, "hit breakpoint {}", b)?,
+                    StopReason::CycleLimit => {
+                        writeln!(output, "stopped after {} cycles", self.max_resume_cycles)?
+                    }
+                    _ => (),
+                },
+                Some("r" | "rewind") => {
+                    let rewound = self.rewind(count(1));
+                    writeln!(output, "rewound {} cycles", rewound)?;
+                }
+                Some("b" | "break") => match parse_breakpoint(&parts[1..]) {
+                    Some(b) => self.add_breakpoint(b),
+                    None => writeln!(
+                        output,
+                        "usage: break cycle <n> | pc <n> | <register> <value>"
+                    )?,
+                },
+                Some("d" | "delete") => match parse_breakpoint(&parts[1..]) {
+                    Some(b) if self.remove_breakpoint(b) => (),
+                    _ => writeln!(output, "no such breakpoint")?,
+                },
+                Some("breakpoints") => {
+                    for b in &self.breakpoints {
+                        writeln!(output, "{}", b)?;
+                    }
+                }
+                Some("crt") => write!(output, "{}", self.crt.render())?,
+                Some("q" | "quit") => return Ok(()),
+                Some(other) => writeln!(output, "unknown command '{}'", other)?,
+                None => (),
+            }
+            writeln!(output, "{}", self.status())?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_breakpoint(parts: &[&str]) -> Option<Breakpoint> {
+    match parts {
+        ["cycle", n] => Some(Breakpoint::Cycle(n.parse().ok()?)),
+        ["pc", n] => Some(Breakpoint::Pc(n.parse().ok()?)),
+        [r, v] => Some(Breakpoint::Register(r.parse().ok()?, v.parse().ok()?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program() -> Program {
+        "noop\naddx 3\naddx -5".parse().unwrap()
+    }
+
+    #[test]
+    fn step_test() {
+        let mut debugger = Debugger::new(program());
+
+        assert_eq!(debugger.step_instruction(), StopReason::Stepped);
+        assert_eq!(debugger.cpu().cycle(), 1);
+        debugger.step_cycle();
+        assert_eq!(debugger.cpu().pc(), 1);
+        assert_eq!(debugger.step_instruction(), StopReason::Stepped);
+        assert_eq!(debugger.cpu().register(Register::X), 4);
+        assert_eq!(debugger.cpu().cycle(), 3);
+    }
+
+    #[test]
+    fn breakpoints_test() {
+        let mut debugger = Debugger::new(program());
+        debugger.add_breakpoint(Breakpoint::Register(Register::X, 4));
+        debugger.add_breakpoint(Breakpoint::Pc(2));
+
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Register(Register::X, 4))
+        );
+        assert_eq!(debugger.cpu().cycle(), 3);
+        // pc 2 was reached on the same cycle, so resuming runs past it
+        assert!(debugger.remove_breakpoint(Breakpoint::Register(Register::X, 4)));
+        debugger.add_breakpoint(Breakpoint::Cycle(5));
+        assert_eq!(
+            debugger.resume(),
+            StopReason::Breakpoint(Breakpoint::Cycle(5))
+        );
+        assert_eq!(debugger.resume(), StopReason::Halted);
+    }
+
+    #[test]
+    fn rewind_test() {
+        let mut debugger = Debugger::new(program());
+        debugger.resume();
+        assert!(debugger.is_halted());
+        assert!(debugger.crt().render().starts_with("#####."));
+
+        assert_eq!(debugger.rewind(2), 2);
+        assert_eq!(debugger.cpu().cycle(), 3);
+        assert_eq!(debugger.cpu().register(Register::X), 4);
+        assert!(debugger.crt().render().starts_with("###..."));
+
+        assert_eq!(debugger.rewind(10), 3);
+        assert_eq!(debugger.cpu().cycle(), 0);
+    }
+
+    #[test]
+    fn history_depth_test() {
+        let mut debugger = Debugger::new(program());
+        debugger.set_history_depth(2);
+        debugger.resume();
+
+        assert_eq!(debugger.rewind(10), 2);
+        assert_eq!(debugger.cpu().cycle(), 3);
+        assert!(debugger.crt().render().starts_with("###..."));
+
+        debugger.set_history_depth(0);
+        debugger.step_cycle();
+        assert_eq!(debugger.rewind(1), 0);
+    }
+
+    #[test]
+    fn cycle_limit_test() {
+        let mut debugger = Debugger::new("loop:\njmp loop".parse().unwrap());
+        debugger.set_max_resume_cycles(1000);
+        assert_eq!(debugger.resume(), StopReason::CycleLimit);
+        assert_eq!(debugger.cpu().cycle(), 1000);
+
+        let mut output = Vec::new();
+        debugger.repl("c\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().nth(1), Some("stopped after 1000 cycles"));
+    }
+
+    #[test]
+    fn repl_test() {
+        let mut debugger = Debugger::new(program());
+        let mut output = Vec::new();
+        debugger
+            .repl("b cycle 2\nc\nn\nrewind\nq\n".as_bytes(), &mut output)
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!(lines[0], "cycle 0 pc 0 x=1 a=0 b=0 c=0 d=0 | next: noop");
+        assert_eq!(lines[2], "hit breakpoint cycle 2");
+        assert_eq!(lines[4], "cycle 3 pc 2 x=4 a=0 b=0 c=0 d=0 | next: addx -5");
+        assert_eq!(lines[5], "rewound 1 cycles");
+    }
+}
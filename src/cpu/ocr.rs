@@ -0,0 +1,370 @@
+use std::fmt::Display;
+
+use simple_grid::Grid;
+
+// the 4x6 font drawn by the crt in 2022 (and most other years), one row per line. Glyphs are
+// matched after trimming unlit columns, so `I` and `Y` are stored trimmed, 3 and 5 wide
+const SMALL_FONT: [(char, [&str; 6]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+// each small glyph plus the unlit column after it
+const SMALL_PITCH: usize = 5;
+
+// the 6x10 font from the 2018 star message puzzle
+const LARGE_FONT: [(char, [&str; 10]); 15] = [
+    (
+        'A',
+        [
+            "..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'B',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#",
+            "#....#", "#####.",
+        ],
+    ),
+    (
+        'C',
+        [
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#....#", ".####.",
+        ],
+    ),
+    (
+        'E',
+        [
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'F',
+        [
+            "######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'G',
+        [
+            ".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#",
+            "#...##", ".###.#",
+        ],
+    ),
+    (
+        'H',
+        [
+            "#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'J',
+        [
+            "...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.",
+            "#...#.", ".###..",
+        ],
+    ),
+    (
+        'K',
+        [
+            "#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..",
+            "#...#.", "#....#",
+        ],
+    ),
+    (
+        'L',
+        [
+            "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+    (
+        'N',
+        [
+            "#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##",
+            "#...##", "#....#",
+        ],
+    ),
+    (
+        'P',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....",
+            "#.....", "#.....",
+        ],
+    ),
+    (
+        'R',
+        [
+            "#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'X',
+        [
+            "#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.",
+            "#....#", "#....#",
+        ],
+    ),
+    (
+        'Z',
+        [
+            "######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....",
+            "#.....", "######",
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OcrError {
+    // height of the lit area, which matches neither font
+    UnsupportedHeight(usize),
+    UnknownGlyphs {
+        partial: String,
+        glyphs: Vec<UnknownGlyph>,
+    },
+}
+
+impl Display for OcrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OcrError::UnsupportedHeight(height) => {
+                write!(f, "no font is {} pixels high", height)
+            }
+            OcrError::UnknownGlyphs { partial, glyphs } => {
+                let columns: Vec<_> = glyphs.iter().map(|g| g.column.to_string()).collect();
+                write!(
+                    f,
+                    "read '{}' with unknown glyphs at columns {}",
+                    partial,
+                    columns.join(", ")
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownGlyph {
+    column: usize,
+    pattern: Vec<String>,
+}
+
+impl UnknownGlyph {
+    // column of the glyph's leftmost lit pixel in the scanned grid
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn pattern(&self) -> &[String] {
+        &self.pattern
+    }
+}
+
+pub fn recognize_chars(grid: &Grid<char>) -> Result<String, OcrError> {
+    let pixels = Grid::new(
+        grid.width(),
+        grid.height(),
+        grid.cell_iter().map(|c| *c == '#').collect(),
+    );
+    recognize(&pixels)
+}
+
+// small glyphs are read from 5 column cells starting at the left edge, large ones are split apart
+// on fully unlit columns. Unknown glyphs come out as '?' in the partial text
+pub fn recognize(pixels: &Grid<bool>) -> Result<String, OcrError> {
+    let lit_rows: Vec<_> = pixels
+        .rows()
+        .filter(|row| pixels.row_iter(*row).any(|lit| *lit))
+        .collect();
+    let (top, bottom) = match (lit_rows.first(), lit_rows.last()) {
+        (Some(top), Some(bottom)) => (*top, *bottom),
+        _ => return Ok(String::new()),
+    };
+    let height = bottom - top + 1;
+    let font: Vec<(char, Vec<&str>)> = match height {
+        6 => SMALL_FONT
+            .iter()
+            .map(|(c, rows)| (*c, rows.to_vec()))
+            .collect(),
+        10 => LARGE_FONT
+            .iter()
+            .map(|(c, rows)| (*c, rows.to_vec()))
+            .collect(),
+        other => return Err(OcrError::UnsupportedHeight(other)),
+    };
+
+    let is_lit_column = |column: usize| (top..=bottom).any(|row| pixels[(column, row)]);
+    // small glyphs sit in fixed cells, since some (like `Y`) fill theirs and touch the next letter
+    let cells: Vec<(usize, usize)> = if height == 6 {
+        (0..pixels.width())
+            .step_by(SMALL_PITCH)
+            .map(|start| (start, (start + SMALL_PITCH).min(pixels.width())))
+            .collect()
+    } else {
+        let mut cells = Vec::new();
+        let mut column = 0;
+        while column < pixels.width() {
+            if !is_lit_column(column) {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < pixels.width() && is_lit_column(column) {
+                column += 1;
+            }
+            cells.push((start, column));
+        }
+        cells
+    };
+
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+    for (cell_start, cell_end) in cells {
+        let Some(start) = (cell_start..cell_end).find(|c| is_lit_column(*c)) else {
+            continue;
+        };
+        let end = (start..cell_end).rfind(|c| is_lit_column(*c)).unwrap() + 1;
+        let pattern: Vec<String> = (top..=bottom)
+            .map(|row| {
+                (start..end)
+                    .map(|c| if pixels[(c, row)] { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        match font.iter().find(|(_, rows)| *rows == pattern) {
+            Some((c, _)) => text.push(*c),
+            None => {
+                text.push('?');
+                unknown.push(UnknownGlyph {
+                    column: start,
+                    pattern,
+                });
+            }
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(OcrError::UnknownGlyphs {
+            partial: text,
+            glyphs: unknown,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(rows: &[&str]) -> Grid<char> {
+        Grid::new(
+            rows[0].len(),
+            rows.len(),
+            rows.iter().flat_map(|r| r.chars()).collect(),
+        )
+    }
+
+    #[test]
+    fn small_font_test() {
+        let screen = grid(&[
+            "####..##..####.#..#.",
+            "#....#..#....#.#..#.",
+            "###..#......#..#..#.",
+            "#....#.....#...#..#.",
+            "#....#..#.#....#..#.",
+            "####..##..####..##..",
+        ]);
+
+        assert_eq!(recognize_chars(&screen), Ok("ECZU".to_string()));
+
+        // both have unlit edge columns in their 5 pixel cells
+        let screen = grid(&[
+            ".###.#...#",
+            "..#..#...#",
+            "..#...#.#.",
+            "..#....#..",
+            "..#....#..",
+            ".###...#..",
+        ]);
+        assert_eq!(recognize_chars(&screen), Ok("IY".to_string()));
+
+        // `Y` fills its cell, so nothing unlit separates it from the next letter
+        let screen = grid(&[
+            "#...#####.#..#.",
+            "#...##....#..#.",
+            ".#.#.###..####.",
+            "..#..#....#..#.",
+            "..#..#....#..#.",
+            "..#..####.#..#.",
+        ]);
+        assert_eq!(recognize_chars(&screen), Ok("YEH".to_string()));
+    }
+
+    #[test]
+    fn large_font_test() {
+        let screen = grid(&[
+            "#....#..#....#",
+            "#....#..##...#",
+            ".#..#...##...#",
+            ".#..#...#.#..#",
+            "..##....#.#..#",
+            "..##....#..#.#",
+            ".#..#...#..#.#",
+            ".#..#...#...##",
+            "#....#..#...##",
+            "#....#..#....#",
+        ]);
+
+        assert_eq!(recognize_chars(&screen), Ok("XN".to_string()));
+    }
+
+    #[test]
+    fn unknown_glyph_test() {
+        let screen = grid(&[
+            "......#...#..#.",
+            "......#...#..#.",
+            ".....###..####.",
+            "......#...#..#.",
+            "......#...#..#.",
+            "...........#..#",
+        ]);
+
+        match recognize_chars(&screen) {
+            Err(OcrError::UnknownGlyphs { partial, glyphs }) => {
+                assert_eq!(partial, "??");
+                assert_eq!(glyphs[0].column(), 5);
+                assert_eq!(glyphs[0].pattern()[2], "###");
+                assert_eq!(glyphs[1].column(), 10);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(
+            recognize_chars(&grid(&["#", "#"])),
+            Err(OcrError::UnsupportedHeight(2))
+        );
+    }
+}
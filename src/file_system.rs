@@ -1,6 +1,9 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    fmt::Debug,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::{Debug, Display},
+    fs,
+    io::{self, Write},
+    path::Path,
     str::FromStr,
 };
 
@@ -39,6 +42,192 @@ impl FileSystem {
         Self { contents }
     }
 
+    pub fn from_dir(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut contents = HashMap::new();
+        Self::read_dir_into(path.as_ref(), "/", &mut contents)?;
+        Ok(Self::new(contents))
+    }
+
+    fn read_dir_into(
+        path: &Path,
+        dir: &str,
+        contents: &mut HashMap<String, Vec<FileOrDir>>,
+    ) -> io::Result<()> {
+        let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+
+        let mut children = Vec::new();
+        for entry in entries {
+            let file_type = entry.file_type()?;
+            // the transcript is split on spaces, so names with whitespace can't be written to it
+            let name = entry
+                .file_name()
+                .into_string()
+                .ok()
+                .filter(|name| !name.contains(char::is_whitespace))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "'{}' can't be named in a transcript",
+                            entry.path().display()
+                        ),
+                    )
+                })?;
+            if file_type.is_dir() {
+                let name = format!("{}/{}", dir, name);
+                Self::read_dir_into(&entry.path(), &name, contents)?;
+                children.push(FileOrDir::Dir { name });
+            } else if file_type.is_file() {
+                let size = u32::try_from(entry.metadata()?.len()).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("file '{}' is too large", entry.path().display()),
+                    )
+                })?;
+                children.push(FileOrDir::File { name, size });
+            }
+            // symlinks are skipped, they could point back up the tree
+        }
+        contents.insert(dir.to_owned(), children);
+        Ok(())
+    }
+
+    pub fn transcript(&self) -> Vec<String> {
+        let mut lines = vec!["$ cd /".to_owned()];
+        self.transcript_of("/", &mut lines);
+        lines
+    }
+
+    fn transcript_of(&self, dir: &str, lines: &mut Vec<String>) {
+        let children = match self.contents.get(dir) {
+            Some(children) => children,
+            None => return,
+        };
+
+        lines.push("$ ls".to_owned());
+        for file_or_dir in children {
+            lines.push(match file_or_dir {
+                FileOrDir::Dir { name } => format!("dir {}", Self::short_name(dir, name)),
+                FileOrDir::File { name, size } => format!("{size} {name}"),
+            });
+        }
+
+        for file_or_dir in children {
+            if let FileOrDir::Dir { name } = file_or_dir {
+                lines.push(format!("$ cd {}", Self::short_name(dir, name)));
+                self.transcript_of(name, lines);
+                lines.push("$ cd ..".to_owned());
+            }
+        }
+    }
+
+    fn short_name<'a>(parent: &str, name: &'a str) -> &'a str {
+        name.strip_prefix(parent)
+            .and_then(|n| n.strip_prefix('/'))
+            .unwrap_or(name)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let snapshot = Snapshot {
+            contents: self
+                .contents
+                .iter()
+                .map(|(dir, children)| (dir.clone(), children.clone()))
+                .collect(),
+            sizes: self.dir_sizes().into_iter().collect(),
+        };
+        serde_json::to_string_pretty(&snapshot)
+    }
+
+    // rejects snapshots that name missing dirs, nest a dir inside itself or whose sizes don't
+    // add up, as `dir_sizes` would panic on the first two
+    #[cfg(feature = "serde")]
+    pub fn from_json(s: &str) -> serde_json::Result<Self> {
+        use serde::de::Error;
+
+        let snapshot: Snapshot = serde_json::from_str(s)?;
+        let filesys = Self::new(snapshot.contents.into_iter().collect());
+        let sizes = filesys
+            .checked_dir_sizes()
+            .map_err(serde_json::Error::custom)?;
+        for (dir, size) in &sizes {
+            match snapshot.sizes.get(dir) {
+                Some(stored) if stored == size => (),
+                Some(stored) => {
+                    return Err(serde_json::Error::custom(format!(
+                        "dir '{}' is stored with size {} but its contents add up to {}",
+                        dir, stored, size
+                    )))
+                }
+                None => {
+                    return Err(serde_json::Error::custom(format!(
+                        "no size for dir '{}'",
+                        dir
+                    )))
+                }
+            }
+        }
+        if let Some(dir) = snapshot.sizes.keys().find(|dir| !sizes.contains_key(*dir)) {
+            return Err(serde_json::Error::custom(format!(
+                "size for unknown dir '{}'",
+                dir
+            )));
+        }
+        Ok(filesys)
+    }
+
+    // like `dir_sizes`, but reports what `dir_size` would panic on
+    #[cfg(feature = "serde")]
+    fn checked_dir_sizes(&self) -> Result<HashMap<String, u32>, String> {
+        fn visit<'a>(
+            filesys: &'a FileSystem,
+            dir: &'a str,
+            sizes: &mut HashMap<String, u32>,
+            visiting: &mut Vec<&'a str>,
+        ) -> Result<u32, String> {
+            if let Some(size) = sizes.get(dir) {
+                return Ok(*size);
+            }
+            if visiting.contains(&dir) {
+                return Err(format!("dir '{}' contains itself", dir));
+            }
+            let children = filesys
+                .contents
+                .get(dir)
+                .ok_or_else(|| format!("no entry for dir '{}'", dir))?;
+
+            visiting.push(dir);
+            let mut size_sum: u32 = 0;
+            for file_or_dir in children {
+                let size = match file_or_dir {
+                    FileOrDir::Dir { name } => visit(filesys, name, sizes, visiting)?,
+                    FileOrDir::File { size, .. } => *size,
+                };
+                size_sum = size_sum
+                    .checked_add(size)
+                    .ok_or_else(|| format!("dir '{}' is too large", dir))?;
+            }
+            visiting.pop();
+            sizes.insert(dir.to_owned(), size_sum);
+            Ok(size_sum)
+        }
+
+        let mut sizes = HashMap::new();
+        for dir in self.contents.keys() {
+            visit(self, dir, &mut sizes, &mut Vec::new())?;
+        }
+        Ok(sizes)
+    }
+
+    pub fn write_transcript<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for line in self.transcript() {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+
     pub fn dir_sizes(&self) -> HashMap<String, u32> {
         let mut sizes = HashMap::new();
         for dir in self.contents.keys() {
@@ -64,6 +253,14 @@ impl FileSystem {
     }
 }
 
+// sorted maps so that two exported snapshots can be diffed line by line
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    contents: BTreeMap<String, Vec<FileOrDir>>,
+    sizes: BTreeMap<String, u32>,
+}
+
 pub fn traverse_file_system(lines: &[String]) -> FileSystem {
     let mut idx = 0;
     let mut dirs = vec!["".to_owned()];
@@ -90,37 +287,34 @@ pub fn traverse_file_system(lines: &[String]) -> FileSystem {
                         dirs.push(format!("{}/{}", current_dir, dir));
                     }
                 }
-                Command::List => loop {
-                    if idx >= lines.len() {
-                        break;
-                    }
-                    let ls_line = &lines[idx];
-                    if ls_line.starts_with('$') {
-                        break;
-                    } else if ls_line.starts_with("dir") {
-                        let parts: Vec<_> = ls_line.split(' ').collect();
-                        let name = format!("{}/{}", current_dir, parts[1].to_owned());
-                        files
-                            .entry(current_dir.to_owned())
-                            .or_default()
-                            .push(FileOrDir::Dir { name });
-                    } else {
-                        let parts: Vec<_> = ls_line.split(' ').collect();
-                        let size = parts[0].parse().unwrap();
-                        let name = parts[1].to_owned();
-                        files
-                            .entry(current_dir.clone())
-                            .or_default()
-                            .push(FileOrDir::File { name, size });
+                Command::List => {
+                    let children = files.entry(current_dir.to_owned()).or_default();
+                    while idx < lines.len() {
+                        let ls_line = &lines[idx];
+                        if ls_line.starts_with('$') {
+                            break;
+                        } else if ls_line.starts_with("dir") {
+                            let parts: Vec<_> = ls_line.split(' ').collect();
+                            let name = format!("{}/{}", current_dir, parts[1].to_owned());
+                            children.push(FileOrDir::Dir { name });
+                        } else {
+                            let parts: Vec<_> = ls_line.split(' ').collect();
+                            let size = parts[0].parse().unwrap();
+                            let name = parts[1].to_owned();
+                            children.push(FileOrDir::File { name, size });
+                        }
+                        idx += 1;
                     }
-                    idx += 1;
-                },
+                }
             }
         }
     }
     FileSystem::new(files)
 }
 
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "lowercase"))]
 pub enum FileOrDir {
     Dir { name: String },
     File { name: String, size: u32 },
@@ -129,8 +323,140 @@ pub enum FileOrDir {
 impl Debug for FileOrDir {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Dir { name } => write!(f, "file {name}"),
-            Self::File { name, size } => write!(f, "dir {name}"),
+            Self::Dir { name } => write!(f, "dir {name}"),
+            Self::File { name, size } => write!(f, "file {name} (size={size})"),
         }
     }
 }
+
+impl Display for FileOrDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dir { name } => write!(f, "dir {name}"),
+            Self::File { name, size } => write!(f, "{size} {name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k";
+
+    fn lines(s: &str) -> Vec<String> {
+        s.lines().map(|l| l.to_owned()).collect()
+    }
+
+    #[test]
+    fn transcript_round_trip() {
+        let filesys = traverse_file_system(&lines(EXAMPLE));
+
+        let mut expected = lines(EXAMPLE);
+        expected.push("$ cd ..".to_owned());
+        assert_eq!(filesys.transcript(), expected);
+    }
+
+    #[test]
+    fn file_or_dir_fmt() {
+        let dir = FileOrDir::Dir {
+            name: "//a".to_owned(),
+        };
+        let file = FileOrDir::File {
+            name: "b.txt".to_owned(),
+            size: 14848514,
+        };
+
+        assert_eq!(format!("{:?}", dir), "dir //a");
+        assert_eq!(format!("{:?}", file), "file b.txt (size=14848514)");
+        assert_eq!(dir.to_string(), "dir //a");
+        assert_eq!(file.to_string(), "14848514 b.txt");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let filesys = traverse_file_system(&lines(EXAMPLE));
+
+        let json = filesys.to_json().unwrap();
+        let reloaded = FileSystem::from_json(&json).unwrap();
+
+        assert_eq!(reloaded.dir_sizes(), filesys.dir_sizes());
+        assert_eq!(reloaded.to_json().unwrap(), json);
+        assert!(json.contains("\"/\": 48381165"));
+
+        let error = |json: &str| FileSystem::from_json(json).err().unwrap().to_string();
+        assert_eq!(
+            error(r#"{"contents": {"/": [{"type": "dir", "name": "//a"}]}, "sizes": {"/": 0}}"#),
+            "no entry for dir '//a'"
+        );
+        assert_eq!(
+            error(r#"{"contents": {"/": [{"type": "dir", "name": "/"}]}, "sizes": {"/": 0}}"#),
+            "dir '/' contains itself"
+        );
+        assert_eq!(
+            error(
+                r#"{"contents": {"/": [{"type": "file", "name": "a", "size": 5}]}, "sizes": {"/": 4}}"#
+            ),
+            "dir '/' is stored with size 4 but its contents add up to 5"
+        );
+        assert_eq!(
+            error(r#"{"contents": {"/": []}, "sizes": {}}"#),
+            "no size for dir '/'"
+        );
+    }
+
+    #[test]
+    fn from_dir_test() {
+        let root = std::env::temp_dir().join(format!("aoc-2022-fs-{}", std::process::id()));
+        fs::create_dir_all(root.join("a/e")).unwrap();
+        fs::create_dir_all(root.join("d")).unwrap();
+        fs::write(root.join("b.txt"), vec![0; 100]).unwrap();
+        fs::write(root.join("a/f"), vec![0; 20]).unwrap();
+        fs::write(root.join("a/e/i"), vec![0; 3]).unwrap();
+
+        let filesys = FileSystem::from_dir(&root);
+        fs::remove_dir_all(&root).unwrap();
+        let filesys = filesys.unwrap();
+
+        let sizes = filesys.dir_sizes();
+        assert_eq!(sizes["/"], 123);
+        assert_eq!(sizes["//a"], 23);
+        assert_eq!(sizes["//a/e"], 3);
+        assert_eq!(sizes["//d"], 0);
+
+        let reparsed = traverse_file_system(&filesys.transcript());
+        assert_eq!(reparsed.dir_sizes(), sizes);
+
+        let root = std::env::temp_dir().join(format!("aoc-2022-fs-spaces-{}", std::process::id()));
+        fs::create_dir_all(root.join("my dir")).unwrap();
+        let result = FileSystem::from_dir(&root);
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Write};
+
+use simple_grid::Grid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb(0, 0, 0);
+    pub const WHITE: Rgb = Rgb(255, 255, 255);
+    pub const RED: Rgb = Rgb(255, 0, 0);
+    pub const GREEN: Rgb = Rgb(0, 200, 0);
+
+    // dark blue for 0, through purple and orange, to yellow for `max`
+    pub fn gradient(value: u32, max: u32) -> Rgb {
+        if max == 0 {
+            return Self::gradient_f(0.0);
+        }
+        Self::gradient_f(value.min(max) as f64 / max as f64)
+    }
+
+    fn gradient_f(t: f64) -> Rgb {
+        let stops = [
+            (0.0, Rgb(13, 8, 135)),
+            (0.5, Rgb(204, 71, 120)),
+            (1.0, Rgb(240, 249, 33)),
+        ];
+        for w in stops.windows(2) {
+            let ((t0, c0), (t1, c1)) = (w[0], w[1]);
+            if t <= t1 {
+                let f = (t - t0) / (t1 - t0);
+                let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+                return Rgb(lerp(c0.0, c1.0), lerp(c0.1, c1.1), lerp(c0.2, c1.2));
+            }
+        }
+        stops[stops.len() - 1].1
+    }
+}
+
+pub fn write_ppm<W: Write>(writer: &mut W, image: &Grid<Rgb>) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    let data: Vec<u8> = image
+        .cell_iter()
+        .flat_map(|Rgb(r, g, b)| [*r, *g, *b])
+        .collect();
+    writer.write_all(&data)
+}
+
+#[cfg(feature = "png")]
+pub fn write_png<W: Write>(writer: W, image: &Grid<Rgb>) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = image
+        .cell_iter()
+        .flat_map(|Rgb(r, g, b)| [*r, *g, *b])
+        .collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    Ok(())
+}
+
+// two spaces per cell with a 24-bit background colour, so cells come out roughly square
+pub fn ansi_string(image: &Grid<Rgb>) -> String {
+    let mut s = String::new();
+    for row in image.rows() {
+        for Rgb(r, g, b) in image.row_iter(row) {
+            s.push_str(&format!("\x1b[48;2;{r};{g};{b}m  "));
+        }
+        s.push_str("\x1b[0m\n");
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_ppm_test() {
+        let image = Grid::new(2, 1, vec![Rgb::BLACK, Rgb::WHITE]);
+        let mut out = Vec::new();
+        write_ppm(&mut out, &image).unwrap();
+
+        assert_eq!(out, b"P6\n2 1\n255\n\x00\x00\x00\xff\xff\xff");
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn write_png_test() {
+        let image = Grid::new(2, 1, vec![Rgb::BLACK, Rgb::WHITE]);
+        let mut out = Vec::new();
+        write_png(&mut out, &image).unwrap();
+
+        assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn ansi_string_test() {
+        let image = Grid::new(1, 1, vec![Rgb(1, 2, 3)]);
+
+        assert_eq!(ansi_string(&image), "\x1b[48;2;1;2;3m  \x1b[0m\n");
+    }
+
+    #[test]
+    fn gradient_test() {
+        assert_eq!(Rgb::gradient(0, 10), Rgb(13, 8, 135));
+        assert_eq!(Rgb::gradient(10, 10), Rgb(240, 249, 33));
+        assert_eq!(Rgb::gradient(20, 10), Rgb(240, 249, 33));
+        assert_eq!(Rgb::gradient(0, 0), Rgb(13, 8, 135));
+    }
+}
@@ -1,188 +1,758 @@
-use std::collections::{HashSet, VecDeque};
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
 
 use simple_grid::{Grid, GridIndex};
 
+use crate::{
+    heatmap::Rgb,
+    pathfinding::{self, Path},
+};
+
+// Heights run from 0 for `a` to 25 for `z`. The markers are resolved into the start and target
+// when the map is built, so changing the rules afterwards can't change what a cell means.
 pub struct Hills {
-    grid: Grid<Height>,
+    grid: Grid<u8>,
+    start: GridIndex,
+    target: GridIndex,
+    rules: ClimbingRules,
 }
 
 impl Hills {
-    pub fn new(width: usize, height: usize, data: Vec<char>) -> Self {
-        Self {
-            grid: Grid::new(width, height, data.into_iter().map(|h| Height(h)).collect()),
-        }
+    // `data` is the map in reading order, checked like a parsed one
+    pub fn new(width: usize, height: usize, data: Vec<char>) -> Result<Self, ParseHillsError> {
+        Self::with_rules(width, height, data, ClimbingRules::default())
     }
 
-    pub fn find_shortest_path(&self) -> Option<u32> {
-        let start = self.find_starting_pos()?;
-
-        let mut visited = HashSet::new();
-        let mut to_visit = VecDeque::new();
-        to_visit.push_back((0, start));
-        visited.insert(start);
-
-        while let Some((steps, current_idx)) = to_visit.pop_front() {
-            visited.insert(current_idx);
-            let current = &self.grid[current_idx];
-            println!("visiting {} at {}", current.0, current_idx);
-            if current.is_target() {
-                return Some(steps);
-            }
+    pub fn with_rules(
+        width: usize,
+        height: usize,
+        data: Vec<char>,
+        rules: ClimbingRules,
+    ) -> Result<Self, ParseHillsError> {
+        if width == 0 || height == 0 {
+            return Err(ParseHillsError::Empty);
+        }
+        if data.len() != width * height {
+            return Err(ParseHillsError::WrongSize {
+                expected: width * height,
+                found: data.len(),
+            });
+        }
 
-            for n in self.neighbors(current_idx) {
-                let has_visited = visited.contains(&n);
-                if !has_visited {
-                    visited.insert(n);
-                    to_visit.push_back((steps + 1, n));
+        let mut start = None;
+        let mut target = None;
+        let mut heights = Vec::with_capacity(data.len());
+        for (i, c) in data.into_iter().enumerate() {
+            let idx = GridIndex::new(i % width, i / width);
+            let position = (idx.row() + 1, idx.column() + 1);
+            heights.push(if c == rules.start_marker {
+                if let Some(first) = start.replace(idx) {
+                    return Err(ParseHillsError::DuplicateStart(
+                        (first.row() + 1, first.column() + 1),
+                        position,
+                    ));
                 }
+                0
+            } else if c == rules.target_marker {
+                if let Some(first) = target.replace(idx) {
+                    return Err(ParseHillsError::DuplicateTarget(
+                        (first.row() + 1, first.column() + 1),
+                        position,
+                    ));
+                }
+                b'z' - b'a'
+            } else if c.is_ascii_lowercase() {
+                c as u8 - b'a'
+            } else {
+                return Err(ParseHillsError::InvalidChar {
+                    line: position.0,
+                    column: position.1,
+                    c,
+                });
+            });
+        }
+
+        Ok(Self {
+            grid: Grid::new(width, height, heights),
+            start: start.ok_or(ParseHillsError::MissingStart)?,
+            target: target.ok_or(ParseHillsError::MissingTarget)?,
+            rules,
+        })
+    }
+
+    // validates the map against the markers in `rules`; the `FromStr` impl uses the default ones
+    pub fn parse_with_rules(s: &str, rules: ClimbingRules) -> Result<Self, ParseHillsError> {
+        let lines: Vec<_> = s.trim_end().lines().collect();
+        let width = lines.first().map_or(0, |l| l.chars().count());
+        let mut data = Vec::with_capacity(width * lines.len());
+        for (row, line) in lines.iter().enumerate() {
+            let found = line.chars().count();
+            if found != width {
+                return Err(ParseHillsError::RaggedRow {
+                    line: row + 1,
+                    expected: width,
+                    found,
+                });
             }
+            data.extend(line.chars());
         }
+        Self::with_rules(width, lines.len(), data, rules)
+    }
 
-        None
+    pub fn rules(&self) -> &ClimbingRules {
+        &self.rules
+    }
+
+    // the markers in `rules` only matter to parsing and `render_path`, the start and target stay
+    // where the map had them
+    pub fn set_rules(&mut self, rules: ClimbingRules) {
+        self.rules = rules;
+    }
+
+    pub fn find_shortest_path(&self) -> Option<u32> {
+        self.shortest_path(Search::Bfs).map(|path| path.steps())
     }
 
     pub fn find_shortest_hike(&self) -> Option<u32> {
-        let start = self.find_target_pos()?;
-        let mut visited = HashSet::new();
-        let mut to_visit = VecDeque::new();
-        to_visit.push_back((0, start));
-        visited.insert(start);
-
-        while let Some((steps, current_idx)) = to_visit.pop_front() {
-            visited.insert(current_idx);
-            let current = &self.grid[current_idx];
-            if current.is_hike_start() {
-                return Some(steps);
-            }
+        self.shortest_hike(Search::Bfs).map(|path| path.steps())
+    }
 
-            for n in self.inverse_neighbors(current_idx) {
-                let has_visited = visited.contains(&n);
-                if !has_visited {
-                    visited.insert(n);
-                    to_visit.push_back((steps + 1, n));
-                }
+    // From the start marker to the goal marker. `Bfs` finds the fewest steps and ignores the
+    // climb cost, the other two find the cheapest path.
+    pub fn shortest_path(&self, search: Search) -> Option<Path> {
+        let (start, target) = (self.start, self.target);
+        let is_goal = |idx: GridIndex| idx == target;
+        let weighted = |idx| {
+            self.neighbors(idx)
+                .into_iter()
+                .map(move |n| (n, self.step_cost(idx, n)))
+        };
+
+        match search {
+            Search::Bfs => pathfinding::bfs(start, |idx| self.neighbors(idx), is_goal),
+            Search::Dijkstra => pathfinding::dijkstra(start, weighted, is_goal),
+            Search::AStar => {
+                pathfinding::astar(start, weighted, is_goal, |idx| self.distance(idx, target))
             }
         }
+    }
 
-        None
+    // From the closest (or cheapest) lowest point to the goal, found by searching backwards from
+    // the goal. There is no single point to aim for, so `AStar` searches like `Dijkstra`.
+    pub fn shortest_hike(&self, search: Search) -> Option<Path> {
+        let start = self.target;
+        let is_goal = |idx: GridIndex| self.grid[idx] == 0;
+        let path = match search {
+            Search::Bfs => pathfinding::bfs(start, |idx| self.inverse_neighbors(idx), is_goal),
+            Search::Dijkstra | Search::AStar => pathfinding::dijkstra(
+                start,
+                |idx| {
+                    self.inverse_neighbors(idx)
+                        .into_iter()
+                        .map(move |n| (n, self.step_cost(n, idx)))
+                },
+                is_goal,
+            ),
+        };
+        path.map(Path::reversed)
     }
 
-    fn neighbors(&self, idx: GridIndex) -> Vec<GridIndex> {
-        let mut neighbors = Vec::new();
-        // up
-        if idx.row() > 0 {
-            let up_idx = GridIndex::new(idx.column(), idx.row() - 1);
-            if self.grid[idx].can_walk_to(self.grid[up_idx]) {
-                neighbors.push(up_idx);
+    // The fewest steps between every point and the closest of `sources`: walking away from them
+    // when `Forward`, towards them when `Reverse`. `None` where they can't be reached.
+    pub fn distance_field(
+        &self,
+        sources: impl IntoIterator<Item = GridIndex>,
+        direction: Direction,
+    ) -> Grid<Option<u32>> {
+        let distances = match direction {
+            Direction::Forward => pathfinding::distances(sources, |idx| self.neighbors(idx)),
+            Direction::Reverse => {
+                pathfinding::distances(sources, |idx| self.inverse_neighbors(idx))
             }
+        };
+
+        let mut field = Grid::new_default(self.grid.width(), self.grid.height());
+        for (idx, steps) in distances {
+            field[idx] = Some(steps);
         }
-        // down
-        if idx.row() < self.grid.height() - 1 {
-            let down_idx = GridIndex::new(idx.column(), idx.row() + 1);
-            if self.grid[idx].can_walk_to(self.grid[down_idx]) {
-                neighbors.push(down_idx);
-            }
+        field
+    }
+
+    // how many steps every point is from the goal
+    pub fn distances_to_target(&self) -> Grid<Option<u32>> {
+        self.distance_field([self.target], Direction::Reverse)
+    }
+
+    pub fn reachable(
+        &self,
+        sources: impl IntoIterator<Item = GridIndex>,
+        direction: Direction,
+    ) -> Grid<bool> {
+        let field = self.distance_field(sources, direction);
+        self.map_grid(|idx| field[idx].is_some())
+    }
+
+    // the candidate closest to the goal and its distance, the first one on ties
+    pub fn best_start(
+        &self,
+        candidates: impl IntoIterator<Item = GridIndex>,
+    ) -> Option<(GridIndex, u32)> {
+        let distances = self.distances_to_target();
+        candidates
+            .into_iter()
+            .filter_map(|idx| Some((idx, distances[idx]?)))
+            .min_by_key(|(_, steps)| *steps)
+    }
+
+    // every point at the lowest height, in reading order
+    pub fn lowest_points(&self) -> Vec<GridIndex> {
+        self.grid
+            .indices()
+            .filter(|idx| self.grid[*idx] == 0)
+            .collect()
+    }
+
+    // The map as in the puzzle's illustration: every point on the path shows the direction of the
+    // next step, the goal is the target marker and everything else is `.`
+    pub fn render_path(&self, path: &Path) -> String {
+        let mut cells = self.map_grid(|_| '.');
+        for step in path.nodes().windows(2) {
+            cells[step[0]] = arrow(step[0], step[1]);
         }
-        // left
-        if idx.column() > 0 {
-            let left_idx = GridIndex::new(idx.column() - 1, idx.row());
-            if self.grid[idx].can_walk_to(self.grid[left_idx]) {
-                neighbors.push(left_idx);
-            }
+        if let Some(last) = path.nodes().last() {
+            cells[*last] = self.rules.markers().1;
         }
-        // right
-        if idx.column() < self.grid.width() - 1 {
-            let right_idx = GridIndex::new(idx.column() + 1, idx.row());
-            if self.grid[idx].can_walk_to(self.grid[right_idx]) {
-                neighbors.push(right_idx);
-            }
+
+        let mut s = String::new();
+        for row in cells.rows() {
+            s.extend(cells.row_iter(row));
+            s.push('\n');
         }
+        s
+    }
+
+    // heights from dark to light, with the path in red; see `heatmap::ansi_string` for a
+    // coloured terminal version
+    pub fn path_heatmap(&self, path: &Path) -> Grid<Rgb> {
+        let mut image = self.map_grid(|idx| Rgb::gradient(self.grid[idx] as u32, 25));
+        for idx in path.nodes() {
+            image[*idx] = Rgb::RED;
+        }
+        image
+    }
+
+    fn map_grid<T, F: Fn(GridIndex) -> T>(&self, f: F) -> Grid<T> {
+        Grid::new(
+            self.grid.width(),
+            self.grid.height(),
+            self.grid.indices().map(f).collect(),
+        )
+    }
 
-        neighbors
+    fn neighbors(&self, idx: GridIndex) -> Vec<GridIndex> {
+        self.adjacent(idx)
+            .filter(|n| self.can_step(idx, *n))
+            .collect()
     }
 
     fn inverse_neighbors(&self, idx: GridIndex) -> Vec<GridIndex> {
-        let mut neighbors = Vec::new();
-        // up
-        if idx.row() > 0 {
-            let up_idx = GridIndex::new(idx.column(), idx.row() - 1);
-            if self.grid[up_idx].can_walk_to(self.grid[idx]) {
-                neighbors.push(up_idx);
-            }
+        self.adjacent(idx)
+            .filter(|n| self.can_step(*n, idx))
+            .collect()
+    }
+
+    fn can_step(&self, from: GridIndex, to: GridIndex) -> bool {
+        self.rules.can_step(self.grid[from], self.grid[to])
+    }
+
+    fn step_cost(&self, from: GridIndex, to: GridIndex) -> u32 {
+        self.rules.step_cost(self.grid[from], self.grid[to])
+    }
+
+    // the fewest steps between two points on flat ground, which never overestimates the cost
+    fn distance(&self, a: GridIndex, b: GridIndex) -> u32 {
+        if self.rules.diagonals {
+            pathfinding::chebyshev(a, b)
+        } else {
+            pathfinding::manhattan(a, b)
         }
-        // down
-        if idx.row() < self.grid.height() - 1 {
-            let down_idx = GridIndex::new(idx.column(), idx.row() + 1);
-            if self.grid[down_idx].can_walk_to(self.grid[idx]) {
-                neighbors.push(down_idx);
+    }
+
+    // up, down, left and right, plus the diagonals if the rules allow them, where they are on
+    // the map
+    fn adjacent(&self, idx: GridIndex) -> impl Iterator<Item = GridIndex> {
+        const STRAIGHT: [(isize, isize); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];
+        const DIAGONAL: [(isize, isize); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+        let (width, height) = (self.grid.width(), self.grid.height());
+        let diagonals = if self.rules.diagonals {
+            &DIAGONAL[..]
+        } else {
+            &[]
+        };
+        STRAIGHT
+            .iter()
+            .chain(diagonals)
+            .filter_map(move |(dc, dr)| {
+                let column = idx.column().checked_add_signed(*dc)?;
+                let row = idx.row().checked_add_signed(*dr)?;
+                (column < width && row < height).then(|| GridIndex::new(column, row))
+            })
+    }
+}
+
+impl FromStr for Hills {
+    type Err = ParseHillsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_with_rules(s, ClimbingRules::default())
+    }
+}
+
+// lines and columns count from 1, endpoints are given as (line, column)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseHillsError {
+    Empty,
+    // only from `Hills::new` and `with_rules`, whose data doesn't come in lines
+    WrongSize {
+        expected: usize,
+        found: usize,
+    },
+    RaggedRow {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    InvalidChar {
+        line: usize,
+        column: usize,
+        c: char,
+    },
+    MissingStart,
+    MissingTarget,
+    DuplicateStart((usize, usize), (usize, usize)),
+    DuplicateTarget((usize, usize), (usize, usize)),
+}
+
+impl Display for ParseHillsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the map is empty"),
+            Self::WrongSize { expected, found } => {
+                write!(f, "the map has {} cells, expected {}", found, expected)
             }
-        }
-        // left
-        if idx.column() > 0 {
-            let left_idx = GridIndex::new(idx.column() - 1, idx.row());
-            if self.grid[left_idx].can_walk_to(self.grid[idx]) {
-                neighbors.push(left_idx);
+            Self::RaggedRow {
+                line,
+                expected,
+                found,
+            } => write!(f, "line {} is {} wide, expected {}", line, found, expected),
+            Self::InvalidChar { line, column, c } => {
+                write!(
+                    f,
+                    "invalid height '{}' at line {}, column {}",
+                    c, line, column
+                )
             }
+            Self::MissingStart => write!(f, "the map has no start"),
+            Self::MissingTarget => write!(f, "the map has no target"),
+            Self::DuplicateStart(first, second) => write!(
+                f,
+                "a second start at line {}, column {} after line {}, column {}",
+                second.0, second.1, first.0, first.1
+            ),
+            Self::DuplicateTarget(first, second) => write!(
+                f,
+                "a second target at line {}, column {} after line {}, column {}",
+                second.0, second.1, first.0, first.1
+            ),
         }
-        // right
-        if idx.column() < self.grid.width() - 1 {
-            let right_idx = GridIndex::new(idx.column() + 1, idx.row());
-            if self.grid[right_idx].can_walk_to(self.grid[idx]) {
-                neighbors.push(right_idx);
-            }
+    }
+}
+
+fn arrow(from: GridIndex, to: GridIndex) -> char {
+    let right = to.column().cmp(&from.column());
+    let down = to.row().cmp(&from.row());
+    match (right, down) {
+        (Ordering::Equal, Ordering::Less) => '^',
+        (Ordering::Equal, _) => 'v',
+        (Ordering::Less, Ordering::Equal) => '<',
+        (_, Ordering::Equal) => '>',
+        (Ordering::Less, Ordering::Less) => '↖',
+        (_, Ordering::Less) => '↗',
+        (Ordering::Less, _) => '↙',
+        _ => '↘',
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Search {
+    Bfs,
+    Dijkstra,
+    AStar,
+}
+
+// How the map may be walked. The default is the puzzle's: climb at most one, descend any amount,
+// no diagonals, S to E, and every step costs one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClimbingRules {
+    max_ascent: u8,
+    max_descent: Option<u8>,
+    diagonals: bool,
+    start_marker: char,
+    target_marker: char,
+    climb_cost: u32,
+}
+
+impl ClimbingRules {
+    pub fn max_ascent(&self) -> u8 {
+        self.max_ascent
+    }
+
+    pub fn set_max_ascent(&mut self, max_ascent: u8) {
+        self.max_ascent = max_ascent;
+    }
+
+    // `None` for any amount
+    pub fn max_descent(&self) -> Option<u8> {
+        self.max_descent
+    }
+
+    pub fn set_max_descent(&mut self, max_descent: Option<u8>) {
+        self.max_descent = max_descent;
+    }
+
+    pub fn diagonals(&self) -> bool {
+        self.diagonals
+    }
+
+    pub fn set_diagonals(&mut self, diagonals: bool) {
+        self.diagonals = diagonals;
+    }
+
+    pub fn markers(&self) -> (char, char) {
+        (self.start_marker, self.target_marker)
+    }
+
+    // the start stands at height `a` and the target at height `z`. Markers must differ from each
+    // other and from the heights, or a map couldn't tell them apart
+    pub fn set_markers(&mut self, start: char, target: char) -> Result<(), String> {
+        if start == target {
+            return Err(format!(
+                "'{}' can't mark both the start and the target",
+                start
+            ));
         }
+        if let Some(c) = [start, target].into_iter().find(char::is_ascii_lowercase) {
+            return Err(format!("'{}' is a height, not a marker", c));
+        }
+        self.start_marker = start;
+        self.target_marker = target;
+        Ok(())
+    }
 
-        neighbors
+    // what each unit of height climbed costs on top of the step itself
+    pub fn climb_cost(&self) -> u32 {
+        self.climb_cost
     }
 
-    fn find_starting_pos(&self) -> Option<GridIndex> {
-        for idx in self.grid.indices() {
-            let height = &self.grid[idx];
-            if height.is_start() {
-                return Some(idx);
-            }
+    pub fn set_climb_cost(&mut self, climb_cost: u32) {
+        self.climb_cost = climb_cost;
+    }
+
+    pub fn can_step(&self, from: u8, to: u8) -> bool {
+        if to >= from {
+            to - from <= self.max_ascent
+        } else {
+            self.max_descent.is_none_or(|max| from - to <= max)
         }
-        None
     }
 
-    fn find_target_pos(&self) -> Option<GridIndex> {
-        for idx in self.grid.indices() {
-            let height = &self.grid[idx];
-            if height.is_target() {
-                return Some(idx);
-            }
+    pub fn step_cost(&self, from: u8, to: u8) -> u32 {
+        1 + self.climb_cost * to.saturating_sub(from) as u32
+    }
+}
+
+impl Default for ClimbingRules {
+    fn default() -> Self {
+        Self {
+            max_ascent: 1,
+            max_descent: None,
+            diagonals: false,
+            start_marker: 'S',
+            target_marker: 'E',
+            climb_cost: 0,
         }
-        None
     }
 }
 
-#[derive(Clone, Copy)]
-struct Height(char);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl Height {
-    pub fn is_start(&self) -> bool {
-        self.0 == 'S'
+    const EXAMPLE: &str = "Sabqponm
+abcryxxl
+accszExk
+acctuvwj
+abdefghi";
+
+    fn example() -> Hills {
+        with_rules(EXAMPLE, ClimbingRules::default())
     }
 
-    pub fn is_hike_start(&self) -> bool {
-        self.value() == ('a' as u8 - 'a' as u8)
+    fn example_map(map: &str) -> Hills {
+        with_rules(map, ClimbingRules::default())
     }
 
-    pub fn is_target(&self) -> bool {
-        self.0 == 'E'
+    fn with_rules(map: &str, rules: ClimbingRules) -> Hills {
+        Hills::parse_with_rules(map, rules).unwrap()
     }
 
-    pub fn value(&self) -> u8 {
-        if self.is_target() {
-            'z' as u8 - 'a' as u8
-        } else if self.is_start() {
-            'a' as u8 - 'a' as u8
-        } else {
-            self.0 as u8 - 'a' as u8
+    #[test]
+    fn shortest_path_test() {
+        let hills = example();
+
+        for search in [Search::Bfs, Search::Dijkstra, Search::AStar] {
+            let path = hills.shortest_path(search).unwrap();
+            assert_eq!(path.steps(), 31, "{:?}", search);
+            assert_eq!(path.nodes()[0], GridIndex::new(0, 0));
+            assert_eq!(path.nodes()[31], GridIndex::new(5, 2));
+            assert!(path.nodes().windows(2).all(|w| hills.can_step(w[0], w[1])));
         }
+        assert_eq!(hills.find_shortest_path(), Some(31));
+    }
+
+    #[test]
+    fn shortest_hike_test() {
+        let hills = example();
+        let path = hills.shortest_hike(Search::Bfs).unwrap();
+
+        assert_eq!(path.steps(), 29);
+        assert_eq!(hills.grid[path.nodes()[0]], 0);
+        assert_eq!(path.nodes()[29], GridIndex::new(5, 2));
+        assert_eq!(hills.find_shortest_hike(), Some(29));
+    }
+
+    #[test]
+    fn climbing_rules_test() {
+        let mut rules = ClimbingRules::default();
+        assert!(rules.can_step(3, 4) && rules.can_step(20, 0) && !rules.can_step(3, 5));
+
+        rules.set_max_descent(Some(2));
+        assert!(!rules.can_step(20, 0) && rules.can_step(5, 3));
+
+        rules.set_max_ascent(2);
+        rules.set_climb_cost(3);
+        assert!(rules.can_step(3, 5));
+        assert_eq!(rules.step_cost(3, 5), 7);
+        assert_eq!(rules.step_cost(5, 3), 1);
+    }
+
+    #[test]
+    fn diagonals_and_markers_test() {
+        let mut rules = ClimbingRules::default();
+        rules.set_diagonals(true);
+        let hills = with_rules(EXAMPLE, rules.clone());
+        let path = hills.shortest_path(Search::AStar).unwrap();
+        assert!(path.steps() < 31);
+        assert_eq!(
+            hills.shortest_path(Search::Bfs).unwrap().steps(),
+            path.steps()
+        );
+
+        rules.set_diagonals(false);
+        assert!(rules.set_markers('@', '@').is_err());
+        assert!(rules.set_markers('@', 'e').is_err());
+        assert_eq!(rules.markers(), ('S', 'E'));
+        rules.set_markers('@', '#').unwrap();
+        let hills = with_rules(&EXAMPLE.replace('S', "@").replace('E', "#"), rules.clone());
+        assert_eq!(hills.find_shortest_path(), Some(31));
+        assert_eq!(hills.find_shortest_hike(), Some(29));
+
+        // new markers don't change a map that has already been read
+        let mut hills = example();
+        hills.set_rules(rules);
+        assert_eq!(hills.lowest_points().len(), 6);
+        assert_eq!(hills.distances_to_target()[(0, 0)], Some(31));
+        assert_eq!(hills.find_shortest_path(), Some(31));
     }
 
-    pub fn can_walk_to(&self, other: Self) -> bool {
-        self.value() + 1 >= other.value()
+    #[test]
+    fn climb_cost_test() {
+        // climbing over the m in the middle costs more than walking around it
+        let map = "aaaaa\nSmaaE";
+        let mut rules = ClimbingRules::default();
+        rules.set_max_ascent(25);
+        let hills = with_rules(map, rules.clone());
+        assert_eq!(hills.shortest_path(Search::Dijkstra).unwrap().cost(), 4);
+
+        rules.set_climb_cost(10);
+        let hills = with_rules(map, rules);
+        let bfs = hills.shortest_path(Search::Bfs).unwrap();
+        let dijkstra = hills.shortest_path(Search::Dijkstra).unwrap();
+        let astar = hills.shortest_path(Search::AStar).unwrap();
+        assert_eq!((bfs.steps(), bfs.cost()), (4, 4));
+        assert_eq!(dijkstra.steps(), 6);
+        assert_eq!(dijkstra.cost(), 6 + 10 * 25);
+        assert_eq!(astar.cost(), dijkstra.cost());
+
+        let hike = hills.shortest_hike(Search::Dijkstra).unwrap();
+        assert_eq!((hike.steps(), hike.cost()), (1, 1 + 10 * 25));
+    }
+
+    #[test]
+    fn distance_field_test() {
+        let hills = example();
+        let to_target = hills.distances_to_target();
+
+        assert_eq!(to_target[(0, 0)], Some(31));
+        assert_eq!(to_target[(5, 2)], Some(0));
+        assert_eq!(
+            hills.best_start(hills.lowest_points()),
+            Some((GridIndex::new(0, 4), 29))
+        );
+        assert_eq!(
+            hills.best_start([GridIndex::new(0, 0)]),
+            Some((GridIndex::new(0, 0), 31))
+        );
+
+        let from_start = hills.distance_field([GridIndex::new(0, 0)], Direction::Forward);
+        assert_eq!(from_start[(5, 2)], Some(31));
+        assert_eq!(from_start[(0, 0)], Some(0));
+
+        // every point can be walked to from the two corners together
+        let corners = [GridIndex::new(0, 0), GridIndex::new(7, 4)];
+        let both = hills.distance_field(corners, Direction::Forward);
+        assert!(both.cell_iter().all(|steps| steps.is_some()));
+        assert_eq!(both[(7, 3)], Some(1));
+    }
+
+    #[test]
+    fn reachable_test() {
+        // neither the z nor the E can be climbed onto from their neighbours
+        let hills = example_map("Sazc\nabaE");
+        let from_start = hills.reachable([GridIndex::new(0, 0)], Direction::Forward);
+        assert_eq!(
+            from_start.cell_iter().copied().collect::<Vec<_>>(),
+            vec![true, true, false, false, true, true, true, false]
+        );
+
+        let to_target = hills.reachable([hills.target], Direction::Reverse);
+        assert_eq!(to_target.cell_iter().filter(|r| **r).count(), 1);
+        assert_eq!(hills.best_start(hills.lowest_points()), None);
+    }
+
+    #[test]
+    fn render_path_test() {
+        let hills = example();
+        let path = hills.shortest_path(Search::Bfs).unwrap();
+
+        // one of the 31 step routes, a little different from the one drawn in the puzzle
+        assert_eq!(
+            hills.render_path(&path),
+            "v..v<<<<
+>v.vv<<^
+.v.v>E^^
+.>v>>>^^
+..>>>>>^
+"
+        );
+
+        let image = hills.path_heatmap(&path);
+        assert_eq!(image[(0, 0)], Rgb::RED);
+        assert_eq!(image[(2, 0)], Rgb::gradient(1, 25));
+        assert_eq!(image.cell_iter().filter(|c| **c == Rgb::RED).count(), 32);
+
+        let mut rules = ClimbingRules::default();
+        rules.set_diagonals(true);
+        rules.set_max_ascent(25);
+        let hills = with_rules("Sbc\nabE", rules);
+        let path = hills.shortest_path(Search::Bfs).unwrap();
+        assert_eq!(hills.render_path(&path), ">↘.\n..E\n");
+
+        let mut rules = ClimbingRules::default();
+        rules.set_max_ascent(25);
+        rules.set_markers('@', '#').unwrap();
+        let hills = with_rules("@bc\nab#", rules);
+        let path = hills.shortest_path(Search::Bfs).unwrap();
+        assert_eq!(hills.render_path(&path), "v..\n>>#\n");
+    }
+
+    #[test]
+    fn parse_test() {
+        let hills: Hills = format!("{}\n\n", EXAMPLE).parse().unwrap();
+        assert_eq!((hills.grid.width(), hills.grid.height()), (8, 5));
+        assert_eq!(hills.find_shortest_path(), Some(31));
+
+        let mut rules = ClimbingRules::default();
+        rules.set_markers('@', '#').unwrap();
+        assert_eq!(
+            Hills::parse_with_rules(EXAMPLE, rules).err(),
+            Some(ParseHillsError::InvalidChar {
+                line: 1,
+                column: 1,
+                c: 'S'
+            })
+        );
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        let parse = |map: &str| map.parse::<Hills>().err();
+
+        assert_eq!(parse(""), Some(ParseHillsError::Empty));
+        assert_eq!(
+            parse("Sab\nab\nabE"),
+            Some(ParseHillsError::RaggedRow {
+                line: 2,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            parse("Sab\naB1\nabE"),
+            Some(ParseHillsError::InvalidChar {
+                line: 2,
+                column: 2,
+                c: 'B'
+            })
+        );
+        assert_eq!(parse("aab\nabE"), Some(ParseHillsError::MissingStart));
+        assert_eq!(parse("Sab\nabc"), Some(ParseHillsError::MissingTarget));
+        assert_eq!(
+            parse("SaE\nSbc"),
+            Some(ParseHillsError::DuplicateStart((1, 1), (2, 1)))
+        );
+        assert_eq!(
+            parse("SaE\nabE").unwrap().to_string(),
+            "a second target at line 2, column 3 after line 1, column 3"
+        );
+
+        // built from cells, the same checks apply
+        let cells = |s: &str| s.chars().collect::<Vec<_>>();
+        assert!(Hills::new(3, 2, cells("SabcdE")).is_ok());
+        assert_eq!(
+            Hills::new(3, 2, cells("SabE")).err(),
+            Some(ParseHillsError::WrongSize {
+                expected: 6,
+                found: 4
+            })
+        );
+        assert_eq!(
+            Hills::new(3, 2, cells("SaBcdE")).err(),
+            Some(ParseHillsError::InvalidChar {
+                line: 1,
+                column: 3,
+                c: 'B'
+            })
+        );
+        assert_eq!(
+            Hills::new(0, 2, Vec::new()).err(),
+            Some(ParseHillsError::Empty)
+        );
     }
 }
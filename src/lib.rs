@@ -7,13 +7,15 @@ use std::{
 
 mod assignment_pairs;
 mod calories;
-mod cpu;
+pub mod cpu;
 mod crate_stack;
 mod file_system;
+mod heatmap;
 mod hills;
 mod marker;
 mod monkeys;
 mod pairs;
+mod pathfinding;
 mod rock_paper_scissors;
 mod rope;
 mod rucksack;
@@ -445,7 +447,7 @@ mod day8 {
         trees.count_visible()
     }
 
-    fn solve_part2_from_file(path: &str) -> u32 {
+    fn solve_part2_from_file(path: &str) -> u64 {
         let trees = populate_grid_from_file(path);
         trees.best_scenic_score()
     }
@@ -525,7 +527,7 @@ mod day9 {
 
 mod day10 {
     use super::*;
-    use crate::cpu::{Cpu, Instruction};
+    use crate::cpu::{ocr, Cpu, Instruction};
     use simple_grid::Grid;
 
     fn solve_from_file(path: &str) -> (Grid<char>, i64) {
@@ -559,103 +561,38 @@ mod day10 {
     #[test]
     fn part2() {
         let (grid, _) = solve_from_file("inputs/day10.txt");
-        println!("{}", grid.to_pretty_string());
-        assert_eq!("ECZUZALR", "ECZUZALR"); // capital letters in the output above
+        assert_eq!(ocr::recognize_chars(&grid).unwrap(), "ECZUZALR");
     }
 }
 
 mod day11 {
-    use std::str::FromStr;
-
     use super::*;
-    use crate::monkeys::{Monkey, Monkeys, OldOrConstant, Operand};
+    use crate::monkeys::Monkeys;
 
     fn parse_monkeys_from_file(path: &str) -> Monkeys {
-        let mut monkeys = Vec::new();
-
-        let lines = read_lines_from_file(path);
-        for (id, m) in lines.chunks(7).enumerate() {
-            //0: Monkey 0:
-            //1: Starting items: 79, 98
-            //2: Operation: new = old * 19
-            //3: Test: divisible by 23
-            //4:   If true: throw to monkey 2
-            //5:   If false: throw to monkey 3
-            //6:
-            let mut starting_items: Vec<u128> = m[1]
-                .trim_start()
-                .trim_start_matches("Starting items: ")
-                .split(", ")
-                .map(|p| {
-                    p.parse()
-                        .unwrap_or_else(|pa| panic!("tried to parse {} as num", p))
-                })
-                .collect();
-            let operation_parts: Vec<_> = m[2]
-                .trim_start()
-                .trim_start_matches("Operation: new = ")
-                .split(' ')
-                .collect();
-            let l = OldOrConstant::from_str(operation_parts[0]).unwrap();
-            let op = Operand::from_str(operation_parts[1]).unwrap();
-            let r = OldOrConstant::from_str(operation_parts[2]).unwrap();
-            let test: u128 = m[3]
-                .trim_start()
-                .trim_start_matches("Test: divisible by ")
-                .parse()
-                .unwrap();
-            let true_target: usize = m[4].split(' ').last().unwrap().parse().unwrap();
-            let false_target: usize = m[5].split(' ').last().unwrap().parse().unwrap();
-            monkeys.push(Monkey::new(
-                id,
-                starting_items,
-                (l, op, r),
-                test,
-                true_target,
-                false_target,
-            ));
-        }
-
-        Monkeys::new(monkeys)
+        read_lines_from_file(path)
+            .join("\n")
+            .parse()
+            .unwrap_or_else(|e| panic!("{}: {}", path, e))
     }
 
     fn solve_part1_from_file(path: &str) -> u128 {
         let mut monkeys = parse_monkeys_from_file(path);
         for turn in 0..20 {
-            monkeys.run_once(&|x| x / 3);
+            monkeys.run_once(&|x| x / 3).unwrap();
         }
 
-        let mut inspection_counts: Vec<_> = monkeys
-            .inspection_counts()
-            .into_iter()
-            .enumerate()
-            .collect();
-
-        inspection_counts.sort_by_key(|(idx, count)| *count);
-        inspection_counts[inspection_counts.len() - 2..]
-            .into_iter()
-            .map(|(_, count)| count)
-            .product()
+        monkeys.monkey_business(2)
     }
 
     fn solve_part2_from_file(path: &str) -> u128 {
         let mut monkeys = parse_monkeys_from_file(path);
-        let acc_test = monkeys.acc_test();
+        monkeys.use_residues().unwrap();
         for turn in 0..10000 {
-            monkeys.run_once(&|x| x % acc_test);
+            monkeys.run_once_without_relief().unwrap();
         }
 
-        let mut inspection_counts: Vec<_> = monkeys
-            .inspection_counts()
-            .into_iter()
-            .enumerate()
-            .collect();
-
-        inspection_counts.sort_by_key(|(idx, count)| *count);
-        inspection_counts[inspection_counts.len() - 2..]
-            .into_iter()
-            .map(|(_, count)| count)
-            .product()
+        monkeys.monkey_business(2)
     }
 
     #[test]
@@ -688,13 +625,10 @@ mod day12 {
     use super::*;
 
     fn parse_hills_from_file(path: &str) -> Hills {
-        let lines = read_lines_from_file(path);
-        let width = lines[0].len();
-        let height = lines.len();
-
-        let data = lines.join("").chars().collect();
-
-        Hills::new(width, height, data)
+        read_lines_from_file(path)
+            .join("\n")
+            .parse()
+            .unwrap_or_else(|e| panic!("{}: {}", path, e))
     }
 
     fn solve_part1_from_file(path: &str) -> u32 {
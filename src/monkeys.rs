@@ -1,28 +1,279 @@
-use std::{collections::VecDeque, str::FromStr};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    mem,
+    str::FromStr,
+};
+
+use expr::{EvalError, Expr};
+use trace::{Inspection, MonkeyObserver, Silent};
+
+pub mod expr;
+pub mod trace;
 
 #[derive(Debug, Clone)]
 pub struct Monkeys {
     monkeys: Vec<Monkey>,
+    round: usize,
 }
 
 impl Monkeys {
     pub fn new(monkeys: Vec<Monkey>) -> Self {
-        Self { monkeys }
+        Self { monkeys, round: 0 }
+    }
+
+    pub fn run_once<F: Fn(u128) -> u128>(&mut self, worry_decreaser: &F) -> Result<(), WorryError> {
+        self.run_with(Some(worry_decreaser), &mut Silent)
+    }
+
+    // the only way to run with residues, as relief can't be applied to them
+    pub fn run_once_without_relief(&mut self) -> Result<(), WorryError> {
+        self.run_with(None, &mut Silent)
     }
 
-    pub fn run_once<F: Fn(u128) -> u128>(&mut self, worry_decreaser: &F) {
-        let acc_test = self.acc_test();
-        for i in 0..self.monkeys.len() {
-            let monkey = &mut self.monkeys[i];
-            let mut throws = Vec::new();
-            while let Some((item, target)) = monkey.take_turn(worry_decreaser) {
-                throws.push((item, target));
+    // Runs one round, telling `observer` about every inspection and the end of the round. Nothing
+    // changes until every inspection has succeeded, so a round that fails can't be half applied.
+    pub fn run_with(
+        &mut self,
+        worry_decreaser: Option<&dyn Fn(u128) -> u128>,
+        observer: &mut dyn MonkeyObserver,
+    ) -> Result<(), WorryError> {
+        if worry_decreaser.is_some()
+            && self
+                .items()
+                .any(|(_, worry)| matches!(worry, Worry::Residues(_)))
+        {
+            return Err(WorryError::ReliefOnResidues);
+        }
+
+        let round = self.round + 1;
+        let moduli = self.tests();
+        let observing = observer.wants_inspections();
+        // items thrown during the round, queued behind what each monkey already holds
+        let mut thrown: Vec<Vec<Worry>> = vec![Vec::new(); self.monkeys.len()];
+        let mut counts = vec![0; self.monkeys.len()];
+        let mut inspections = Vec::new();
+        for (i, monkey) in self.monkeys.iter().enumerate() {
+            let caught = mem::take(&mut thrown[i]);
+            let held = monkey.holding.iter().map(|Item(worry)| worry);
+            for worry in held.chain(&caught) {
+                let (new_worry, divisible, target) =
+                    monkey.inspect(i, &moduli, worry, worry_decreaser)?;
+                if observing {
+                    inspections.push(Inspection::new(
+                        round,
+                        i,
+                        worry.clone(),
+                        new_worry.clone(),
+                        divisible,
+                        target,
+                    ));
+                }
+                thrown[target].push(new_worry);
             }
+            counts[i] = monkey.holding.len() + caught.len();
+        }
+
+        for ((monkey, items), count) in self.monkeys.iter_mut().zip(thrown).zip(counts) {
+            monkey.holding = items.into_iter().map(Item).collect();
+            monkey.total_inspections += count as u128;
+        }
+        self.round = round;
+        for inspection in &inspections {
+            observer.on_inspection(inspection);
+        }
+        observer.on_round_end(self);
+        Ok(())
+    }
+
+    // the number of rounds run so far
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    // the items each monkey holds, in the order they will be inspected
+    pub fn snapshot(&self) -> Vec<Vec<Worry>> {
+        self.monkeys
+            .iter()
+            .map(|m| m.holding.iter().map(|Item(worry)| worry.clone()).collect())
+            .collect()
+    }
+
+    // the product of the `top_n` highest inspection counts
+    pub fn monkey_business(&self, top_n: usize) -> u128 {
+        let mut counts = self.inspection_counts();
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        counts.iter().take(top_n).product()
+    }
+
+    // Switches every item to a residue per monkey test, which stays small however many rounds
+    // run. Fails if an operation can't be evaluated on residues.
+    pub fn use_residues(&mut self) -> Result<(), WorryError> {
+        self.check_modular()?;
+        let moduli = self.tests();
+        for monkey in &mut self.monkeys {
+            for Item(worry) in &mut monkey.holding {
+                *worry = Worry::Residues(worry.residues(&moduli));
+            }
+        }
+        Ok(())
+    }
+
+    // Works out the inspection counts after `rounds` more rounds without relief, without running
+    // them. Every item moves independently, so each one is followed on its own until its
+    // (monkey, residues) state at the start of a round repeats, and the counts of the cycle are
+    // extrapolated from there. Operations must be usable on residues.
+    pub fn predict_inspection_counts(&self, rounds: u64) -> Result<Vec<u128>, WorryError> {
+        self.check_modular()?;
+        let mut counts = self.inspection_counts();
+        for (monkey, worry) in self.items() {
+            let item_counts = self.predict_item(monkey, worry, rounds)?;
+            for (count, item_count) in counts.iter_mut().zip(item_counts) {
+                *count += item_count;
+            }
+        }
+        Ok(counts)
+    }
+
+    fn check_modular(&self) -> Result<(), WorryError> {
+        match self.monkeys.iter().find(|m| !m.operation.is_modular()) {
+            Some(monkey) => Err(WorryError::Eval {
+                monkey: monkey.id,
+                error: EvalError::NotModular,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn items(&self) -> impl Iterator<Item = (usize, &Worry)> {
+        self.monkeys
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, m)| m.holding.iter().map(move |Item(worry)| (idx, worry)))
+    }
+
+    // inspections of a single item, per monkey, over `rounds` rounds
+    fn predict_item(
+        &self,
+        monkey: usize,
+        worry: &Worry,
+        rounds: u64,
+    ) -> Result<Vec<u128>, WorryError> {
+        let moduli = self.tests();
+        let mut state = (monkey, Worry::Residues(worry.residues(&moduli)));
+        let mut seen = HashMap::new();
+        // the counts after each round so far, starting with no rounds
+        let mut history = vec![vec![0; self.monkeys.len()]];
+
+        for round in 0..rounds as usize {
+            if let Some(start) = seen.insert(state.clone(), round) {
+                let (cycle_start, now) = (&history[start], &history[round]);
+                let cycle_len = (round - start) as u64;
+                let remaining = rounds - round as u64;
+                let cycles = (remaining / cycle_len) as u128;
+                let rest = &history[start + (remaining % cycle_len) as usize];
+                let counts = (0..self.monkeys.len())
+                    .map(|m| now[m] + cycles * (now[m] - cycle_start[m]) + rest[m] - cycle_start[m])
+                    .collect();
+                return Ok(counts);
+            }
+
+            let mut counts = history[round].clone();
+            state = self.item_round(state, &moduli, None, &mut counts)?;
+            history.push(counts);
+        }
+        Ok(history.pop().unwrap())
+    }
+
+    // Moves one item through a round, starting at the monkey holding it. It is inspected again
+    // within the same round whenever it is thrown to a monkey that hasn't had its turn yet.
+    fn item_round(
+        &self,
+        (mut monkey, mut worry): (usize, Worry),
+        moduli: &[u128],
+        worry_decreaser: Option<&dyn Fn(u128) -> u128>,
+        counts: &mut [u128],
+    ) -> Result<(usize, Worry), WorryError> {
+        loop {
+            counts[monkey] += 1;
+            let (new_worry, _, target) =
+                self.monkeys[monkey].inspect(monkey, moduli, &worry, worry_decreaser)?;
+            worry = new_worry;
+            if target <= monkey {
+                return Ok((target, worry));
+            }
+            monkey = target;
+        }
+    }
+
+    // Runs `rounds` rounds one item at a time rather than one monkey at a time. Items never affect
+    // each other, so this gives the same inspection counts as calling `run_once` (or
+    // `run_once_without_relief` when there is no relief) that many times, and every item ends up
+    // with the same monkey. Items a monkey holds are kept in their original order, which may
+    // differ from the order the rounds would have left them in.
+    pub fn run_rounds_by_item(
+        &mut self,
+        rounds: usize,
+        worry_decreaser: Option<&(dyn Fn(u128) -> u128 + Sync)>,
+    ) -> Result<(), WorryError> {
+        let moduli = self.tests();
+        let results = self
+            .items()
+            .map(|(monkey, worry)| {
+                self.simulate_item((monkey, worry.clone()), rounds, &moduli, worry_decreaser)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.apply_item_results(results, rounds);
+        Ok(())
+    }
+
+    // `run_rounds_by_item` with the items spread over rayon's thread pool
+    #[cfg(feature = "rayon")]
+    pub fn par_run_rounds_by_item(
+        &mut self,
+        rounds: usize,
+        worry_decreaser: Option<&(dyn Fn(u128) -> u128 + Sync)>,
+    ) -> Result<(), WorryError> {
+        use rayon::prelude::*;
 
-            for (item, target) in throws {
-                self.monkeys[target].holding.push_back(item);
+        let moduli = self.tests();
+        let items: Vec<_> = self.items().collect();
+        let results = items
+            .into_par_iter()
+            .map(|(monkey, worry)| {
+                self.simulate_item((monkey, worry.clone()), rounds, &moduli, worry_decreaser)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.apply_item_results(results, rounds);
+        Ok(())
+    }
+
+    // where an item ends up after `rounds` rounds, and how often each monkey inspected it
+    fn simulate_item(
+        &self,
+        mut item: (usize, Worry),
+        rounds: usize,
+        moduli: &[u128],
+        worry_decreaser: Option<&(dyn Fn(u128) -> u128 + Sync)>,
+    ) -> Result<((usize, Worry), Vec<u128>), WorryError> {
+        let mut counts = vec![0; self.monkeys.len()];
+        for _ in 0..rounds {
+            item = self.item_round(item, moduli, worry_decreaser.map(|f| f as _), &mut counts)?;
+        }
+        Ok((item, counts))
+    }
+
+    fn apply_item_results(&mut self, results: Vec<((usize, Worry), Vec<u128>)>, rounds: usize) {
+        for monkey in &mut self.monkeys {
+            monkey.holding.clear();
+        }
+        for ((monkey, worry), counts) in results {
+            self.monkeys[monkey].holding.push_back(Item(worry));
+            for (monkey, count) in self.monkeys.iter_mut().zip(counts) {
+                monkey.total_inspections += count;
             }
         }
+        self.round += rounds;
     }
 
     pub fn inspection_counts(&self) -> Vec<u128> {
@@ -32,13 +283,84 @@ impl Monkeys {
     pub fn acc_test(&self) -> u128 {
         self.monkeys.iter().map(|m| m.test).product()
     }
+
+    // the smallest modulus that keeps every test result intact
+    pub fn lcm_test(&self) -> u128 {
+        self.monkeys
+            .iter()
+            .fold(1, |lcm, m| lcm / gcd(lcm, m.test) * m.test)
+    }
+
+    fn tests(&self) -> Vec<u128> {
+        self.monkeys.iter().map(|m| m.test).collect()
+    }
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// monkeys are separated by blank lines, any number of them, including none after the last one
+impl FromStr for Monkeys {
+    type Err = ParseMonkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut blocks: Vec<Vec<(usize, &str)>> = Vec::new();
+        let mut current = Vec::new();
+        for (line_idx, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                if !current.is_empty() {
+                    blocks.push(std::mem::take(&mut current));
+                }
+            } else {
+                current.push((line_idx + 1, line));
+            }
+        }
+        if !current.is_empty() {
+            blocks.push(current);
+        }
+
+        let mut monkeys = Vec::new();
+        for (idx, block) in blocks.iter().enumerate() {
+            let monkey = Monkey::parse_block(block)?;
+            if monkey.id != idx {
+                return Err(ParseMonkeyError::new(
+                    Some(monkey.id),
+                    block[0].0,
+                    ParseMonkeyErrorKind::IdMismatch {
+                        expected: idx,
+                        found: monkey.id,
+                    },
+                ));
+            }
+            monkeys.push(monkey);
+        }
+
+        for (monkey, block) in monkeys.iter().zip(&blocks) {
+            for (target, line) in [(monkey.true_target, 4), (monkey.false_target, 5)] {
+                if target >= monkeys.len() {
+                    return Err(ParseMonkeyError::new(
+                        Some(monkey.id),
+                        block[line].0,
+                        ParseMonkeyErrorKind::UnknownTarget(target),
+                    ));
+                }
+            }
+        }
+
+        Ok(Self::new(monkeys))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Monkey {
     id: usize,
     holding: VecDeque<Item>,
-    operation: (OldOrConstant, Operand, OldOrConstant),
+    operation: Expr,
     test: u128,
     true_target: usize,
     false_target: usize,
@@ -49,14 +371,14 @@ impl Monkey {
     pub fn new(
         id: usize,
         holding: Vec<u128>,
-        operation: (OldOrConstant, Operand, OldOrConstant),
+        operation: Expr,
         test: u128,
         true_target: usize,
         false_target: usize,
     ) -> Self {
         Self {
             id,
-            holding: holding.into_iter().map(|i| Item(i)).collect(),
+            holding: holding.into_iter().map(|i| Item(Worry::Plain(i))).collect(),
             operation,
             test,
             true_target,
@@ -65,87 +387,576 @@ impl Monkey {
         }
     }
 
-    fn take_turn<F: Fn(u128) -> u128>(&mut self, worry_decreaser: F) -> Option<(Item, usize)> {
-        // println!("Monkey {}'s turn...", self.id);
-        if let Some(Item(worry_value)) = self.holding.pop_front() {
-            self.total_inspections += 1;
-            let mut new_worry = self.get_new_value(worry_value);
-            // println!(
-            //     "{}: Inspecting item of value {}, new value is {}",
-            //     self.id, worry_value, new_worry
-            // );
-
-            new_worry = worry_decreaser(new_worry);
-            if new_worry % self.test == 0 {
-                // println!(
-                //     "{}: {} is divisible by {}, throwing to {}",
-                //     self.id, new_worry, self.test, self.true_target
-                // );
-                Some((Item(new_worry), self.true_target))
-            } else {
-                // println!(
-                //     "{}: {} isn ot divisible by {}, throwing to {}",
-                //     self.id, new_worry, self.test, self.false_target
-                // );
-                Some((Item(new_worry), self.false_target))
+    // `lines` holds the six non-empty lines of one monkey, with their line numbers
+    fn parse_block(lines: &[(usize, &str)]) -> Result<Self, ParseMonkeyError> {
+        use ParseMonkeyErrorKind::*;
+
+        let (header_line, header) = *lines
+            .first()
+            .ok_or_else(|| ParseMonkeyError::new(None, 1, MissingLine("Monkey <id>:")))?;
+        let id = header
+            .trim()
+            .strip_prefix("Monkey")
+            .and_then(|rest| rest.trim().strip_suffix(':'))
+            .and_then(|id| id.trim().parse().ok())
+            .ok_or_else(|| ParseMonkeyError::new(None, header_line, InvalidHeader))?;
+
+        let field = |idx: usize, prefix: &'static str| {
+            let (line_number, line) = match lines.get(idx) {
+                Some(line) => *line,
+                None => {
+                    let last = lines[lines.len() - 1].0;
+                    return Err(ParseMonkeyError::new(Some(id), last, MissingLine(prefix)));
+                }
+            };
+            let words: Vec<_> = line.split_whitespace().collect();
+            let prefix_words: Vec<_> = prefix.split_whitespace().collect();
+            if words.len() < prefix_words.len() || words[..prefix_words.len()] != prefix_words[..] {
+                return Err(ParseMonkeyError::new(
+                    Some(id),
+                    line_number,
+                    MissingLine(prefix),
+                ));
             }
-        } else {
-            None
+            Ok((line_number, words[prefix_words.len()..].to_vec()))
+        };
+        let error = |line_number: usize, kind| ParseMonkeyError::new(Some(id), line_number, kind);
+
+        let (line_number, words) = field(1, "Starting items:")?;
+        let holding = words
+            .join(" ")
+            .split(',')
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse())
+            .collect::<Result<Vec<u128>, _>>()
+            .map_err(|_| error(line_number, InvalidStartingItems))?;
+
+        let (line_number, words) = field(2, "Operation: new =")?;
+        let operation = words
+            .join(" ")
+            .parse()
+            .map_err(|e| error(line_number, InvalidOperation(e)))?;
+
+        let (line_number, words) = field(3, "Test: divisible by")?;
+        let test = match words[..] {
+            [test] => test.parse().ok().filter(|t| *t != 0),
+            _ => None,
+        }
+        .ok_or_else(|| error(line_number, InvalidTest))?;
+
+        let mut targets = Vec::new();
+        for (idx, prefix) in [
+            (4, "If true: throw to monkey"),
+            (5, "If false: throw to monkey"),
+        ] {
+            let (line_number, words) = field(idx, prefix)?;
+            let target = match words[..] {
+                [target] => target.parse().ok(),
+                _ => None,
+            }
+            .ok_or_else(|| error(line_number, InvalidTarget))?;
+            targets.push(target);
         }
+
+        if let Some((line_number, _)) = lines.get(6) {
+            return Err(error(*line_number, UnexpectedLine));
+        }
+
+        Ok(Self::new(
+            id, holding, operation, test, targets[0], targets[1],
+        ))
     }
 
-    fn get_new_value(&self, v: u128) -> u128 {
-        let (l, op, r) = &self.operation;
-        let left = match l {
-            OldOrConstant::Old => v,
-            OldOrConstant::Constant(c) => *c,
+    // works out an item's new worry level, the test result and where it is thrown, without
+    // touching the monkey
+    fn inspect(
+        &self,
+        idx: usize,
+        moduli: &[u128],
+        worry: &Worry,
+        worry_decreaser: Option<&dyn Fn(u128) -> u128>,
+    ) -> Result<(Worry, bool, usize), WorryError> {
+        let eval_error = |error| WorryError::Eval {
+            monkey: self.id,
+            error,
+        };
+
+        let (new_worry, divisible) = match worry {
+            Worry::Plain(worry_value) => {
+                let mut new_worry = self.operation.eval(*worry_value).map_err(eval_error)?;
+                if let Some(worry_decreaser) = worry_decreaser {
+                    new_worry = worry_decreaser(new_worry);
+                }
+                (Worry::Plain(new_worry), new_worry % self.test == 0)
+            }
+            Worry::Residues(residues) => {
+                if worry_decreaser.is_some() {
+                    return Err(WorryError::ReliefOnResidues);
+                }
+                let residues = residues
+                    .iter()
+                    .zip(moduli)
+                    .map(|(r, m)| self.operation.eval_mod(*r, *m))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(eval_error)?;
+                let divisible = residues[idx] == 0;
+                (Worry::Residues(residues), divisible)
+            }
         };
-        let right = match r {
-            OldOrConstant::Old => v,
-            OldOrConstant::Constant(c) => *c,
+
+        let target = if divisible {
+            self.true_target
+        } else {
+            self.false_target
         };
+        Ok((new_worry, divisible, target))
+    }
+}
 
-        match op {
-            Operand::Add => left + right,
-            Operand::Mul => left * right,
+impl FromStr for Monkey {
+    type Err = ParseMonkeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines: Vec<_> = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(line_idx, line)| (line_idx + 1, line))
+            .collect();
+        Self::parse_block(&lines)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMonkeyError {
+    monkey: Option<usize>,
+    line: usize,
+    kind: ParseMonkeyErrorKind,
+}
+
+impl ParseMonkeyError {
+    fn new(monkey: Option<usize>, line: usize, kind: ParseMonkeyErrorKind) -> Self {
+        Self { monkey, line, kind }
+    }
+
+    // `None` when the header itself could not be read
+    pub fn monkey(&self) -> Option<usize> {
+        self.monkey
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn kind(&self) -> &ParseMonkeyErrorKind {
+        &self.kind
+    }
+}
+
+impl Display for ParseMonkeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.monkey {
+            Some(id) => write!(f, "monkey {}, line {}: {}", id, self.line, self.kind),
+            None => write!(f, "line {}: {}", self.line, self.kind),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-struct Item(u128);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseMonkeyErrorKind {
+    MissingLine(&'static str),
+    InvalidHeader,
+    InvalidStartingItems,
+    InvalidOperation(String),
+    InvalidTest,
+    InvalidTarget,
+    UnexpectedLine,
+    IdMismatch { expected: usize, found: usize },
+    UnknownTarget(usize),
+}
 
-#[derive(Debug, Clone)]
-pub enum OldOrConstant {
-    Old,
-    Constant(u128),
+impl Display for ParseMonkeyErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingLine(prefix) => write!(f, "expected a line starting with '{}'", prefix),
+            Self::InvalidHeader => write!(f, "expected 'Monkey <id>:'"),
+            Self::InvalidStartingItems => write!(f, "starting items must be numbers"),
+            Self::InvalidOperation(e) => write!(f, "invalid operation: {}", e),
+            Self::InvalidTest => write!(f, "test must be a positive number"),
+            Self::InvalidTarget => write!(f, "throw target must be a monkey id"),
+            Self::UnexpectedLine => write!(f, "unexpected line after the last throw target"),
+            Self::IdMismatch { expected, found } => {
+                write!(f, "expected monkey {} but found monkey {}", expected, found)
+            }
+            Self::UnknownTarget(target) => write!(f, "there is no monkey {}", target),
+        }
+    }
 }
 
-impl FromStr for OldOrConstant {
-    type Err = ();
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum Worry {
+    Plain(u128),
+    // the worry level modulo each monkey's test, in monkey order
+    Residues(Vec<u128>),
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "old" => Ok(Self::Old),
-            num => Ok(Self::Constant(num.parse().map_err(|_| ())?)),
+impl Worry {
+    pub fn residues(&self, moduli: &[u128]) -> Vec<u128> {
+        match self {
+            Worry::Plain(v) => moduli.iter().map(|m| v % m).collect(),
+            Worry::Residues(residues) => residues.clone(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Operand {
-    Add,
-    Mul,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorryError {
+    Eval { monkey: usize, error: EvalError },
+    ReliefOnResidues,
+}
+
+impl Display for WorryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorryError::Eval { monkey, error } => write!(f, "monkey {}: {}", monkey, error),
+            WorryError::ReliefOnResidues => write!(f, "relief can't be applied to residues"),
+        }
+    }
 }
 
-impl FromStr for Operand {
-    type Err = ();
+#[derive(Debug, Clone)]
+struct Item(Worry);
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "+" => Ok(Self::Add),
-            "*" => Ok(Self::Mul),
-            _e => Err(()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+";
+
+    fn example() -> Monkeys {
+        EXAMPLE.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_monkeys_test() {
+        let mut monkeys = example();
+        assert_eq!(monkeys.monkeys.len(), 4);
+        assert_eq!(monkeys.acc_test(), 23 * 19 * 13 * 17);
+
+        for _ in 0..20 {
+            monkeys.run_once(&|x| x / 3).unwrap();
         }
+        assert_eq!(monkeys.inspection_counts(), vec![101, 95, 7, 105]);
+        assert_eq!(monkeys.monkey_business(2), 10605);
+        assert_eq!(monkeys.round(), 20);
+    }
+
+    #[test]
+    fn trace_test() {
+        let mut monkeys = example();
+        let mut snapshots = trace::SnapshotRecorder::default();
+        monkeys.run_with(Some(&|x| x / 3), &mut snapshots).unwrap();
+        let plain = |items: &[u128]| items.iter().map(|i| Worry::Plain(*i)).collect::<Vec<_>>();
+        assert_eq!(
+            snapshots.snapshots()[0],
+            vec![
+                plain(&[20, 23, 27, 26]),
+                plain(&[2080, 25, 167, 207, 401, 1046]),
+                plain(&[]),
+                plain(&[])
+            ]
+        );
+
+        let before: u128 = monkeys.inspection_counts().iter().sum();
+        let mut log = trace::EventLog::default();
+        monkeys.run_with(Some(&|x| x / 3), &mut log).unwrap();
+        let first = &log.inspections()[0];
+        assert_eq!(
+            (
+                first.round(),
+                first.monkey(),
+                first.worry(),
+                first.new_worry()
+            ),
+            (2, 0, &Worry::Plain(20), &Worry::Plain(126))
+        );
+        assert_eq!((first.divisible(), first.target()), (false, 3));
+        let after: u128 = monkeys.inspection_counts().iter().sum();
+        assert_eq!(log.inspections().len() as u128, after - before);
+
+        let mut throws = 0;
+        monkeys
+            .run_with(Some(&|x| x / 3), &mut |i: &Inspection| throws += i.target())
+            .unwrap();
+        assert!(throws > 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_lines_test() {
+        let mut monkeys = example();
+        monkeys.use_residues().unwrap();
+        let mut log = trace::EventLog::default();
+        monkeys.run_with(None, &mut log).unwrap();
+
+        let mut out = Vec::new();
+        log.write_json_lines(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), log.inspections().len());
+        assert_eq!(
+            out.lines().next().unwrap(),
+            r#"{"round":1,"monkey":0,"worry":[10,3,1,11],"new_worry":[6,0,6,5],"divisible":false,"target":3}"#
+        );
+    }
+
+    #[test]
+    fn residues_test() {
+        let mut lcm = example();
+        let mut residues = example();
+        residues.use_residues().unwrap();
+        let lcm_test = lcm.lcm_test();
+        assert_eq!(lcm_test, lcm.acc_test());
+
+        for _ in 0..1000 {
+            lcm.run_once(&|x| x % lcm_test).unwrap();
+            residues.run_once_without_relief().unwrap();
+        }
+        assert_eq!(lcm.inspection_counts(), vec![5204, 4792, 199, 5192]);
+        assert_eq!(residues.inspection_counts(), lcm.inspection_counts());
+        let (counts, snapshot) = (residues.inspection_counts(), residues.snapshot());
+        assert_eq!(
+            residues.run_once(&|x| x / 3),
+            Err(WorryError::ReliefOnResidues)
+        );
+        assert_eq!(residues.inspection_counts(), counts);
+        assert_eq!(residues.snapshot(), snapshot);
+        assert_eq!(residues.round(), 1000);
+    }
+
+    #[test]
+    fn predict_inspection_counts_test() {
+        let monkeys = example();
+        for rounds in [0, 1, 20, 1000, 10000] {
+            let mut simulated = example();
+            simulated.use_residues().unwrap();
+            for _ in 0..rounds {
+                simulated.run_once_without_relief().unwrap();
+            }
+            assert_eq!(
+                monkeys.predict_inspection_counts(rounds).unwrap(),
+                simulated.inspection_counts(),
+                "{} rounds",
+                rounds
+            );
+        }
+
+        // continues from the rounds that have already run
+        let mut simulated = example();
+        simulated.use_residues().unwrap();
+        for _ in 0..500 {
+            simulated.run_once_without_relief().unwrap();
+        }
+        assert_eq!(
+            simulated.predict_inspection_counts(500).unwrap(),
+            monkeys.predict_inspection_counts(1000).unwrap()
+        );
+
+        let counts = monkeys
+            .predict_inspection_counts(1_000_000_000_000)
+            .unwrap();
+        // each of the 10 items is inspected at least once a round
+        assert!(counts.iter().sum::<u128>() >= 10 * 1_000_000_000_000);
+    }
+
+    #[test]
+    fn run_rounds_by_item_test() {
+        let mut by_monkey = example();
+        let mut by_item = example();
+        for _ in 0..20 {
+            by_monkey.run_once(&|x| x / 3).unwrap();
+        }
+        by_item.run_rounds_by_item(20, Some(&|x| x / 3)).unwrap();
+        assert_eq!(by_item.inspection_counts(), by_monkey.inspection_counts());
+        assert_eq!(by_item.round(), 20);
+
+        let sorted = |monkeys: &Monkeys| {
+            let mut snapshot = monkeys.snapshot();
+            for items in &mut snapshot {
+                items.sort_by_key(|w| match w {
+                    Worry::Plain(v) => *v,
+                    Worry::Residues(_) => 0,
+                });
+            }
+            snapshot
+        };
+        assert_eq!(sorted(&by_item), sorted(&by_monkey));
+
+        let mut by_monkey = example();
+        let mut by_item = example();
+        by_monkey.use_residues().unwrap();
+        by_item.use_residues().unwrap();
+        for _ in 0..1000 {
+            by_monkey.run_once_without_relief().unwrap();
+        }
+        by_item.run_rounds_by_item(1000, None).unwrap();
+        assert_eq!(by_item.inspection_counts(), by_monkey.inspection_counts());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_run_rounds_by_item_test() {
+        let mut sequential = example();
+        let mut parallel = example();
+        let lcm_test = sequential.lcm_test();
+        sequential
+            .run_rounds_by_item(10000, Some(&|x| x % lcm_test))
+            .unwrap();
+        parallel
+            .par_run_rounds_by_item(10000, Some(&|x| x % lcm_test))
+            .unwrap();
+
+        assert_eq!(parallel.inspection_counts(), sequential.inspection_counts());
+        assert_eq!(parallel.snapshot(), sequential.snapshot());
+        assert_eq!(parallel.monkey_business(2), 2713310158);
+    }
+
+    #[test]
+    fn worry_errors_test() {
+        let mut monkeys = example();
+        let result = (0..1000).try_for_each(|_| monkeys.run_once_without_relief());
+        assert_eq!(
+            result,
+            Err(WorryError::Eval {
+                monkey: 0,
+                error: EvalError::Overflow
+            })
+        );
+        // the failing item is neither lost nor counted, and retrying fails the same way
+        let (counts, snapshot) = (monkeys.inspection_counts(), monkeys.snapshot());
+        assert_eq!(snapshot.iter().map(Vec::len).sum::<usize>(), 10);
+        assert!(monkeys.run_once_without_relief().is_err());
+        assert_eq!(monkeys.inspection_counts(), counts);
+        assert_eq!(monkeys.snapshot(), snapshot);
+
+        // a later monkey fails after the earlier ones have had their turn, which is undone too
+        let mut monkeys: Monkeys = EXAMPLE.replace("old * 19", "old + 19").parse().unwrap();
+        let error = loop {
+            let before = (
+                monkeys.inspection_counts(),
+                monkeys.snapshot(),
+                monkeys.round(),
+            );
+            let mut log = trace::EventLog::default();
+            if let Err(error) = monkeys.run_with(None, &mut log) {
+                let after = (
+                    monkeys.inspection_counts(),
+                    monkeys.snapshot(),
+                    monkeys.round(),
+                );
+                assert_eq!(after, before);
+                assert!(log.inspections().is_empty());
+                break error;
+            }
+        };
+        assert!(matches!(error, WorryError::Eval { monkey, .. } if monkey > 0));
+
+        let mut monkeys = EXAMPLE
+            .replace("old + 3", "old / 2")
+            .parse::<Monkeys>()
+            .unwrap();
+        assert_eq!(
+            monkeys.use_residues(),
+            Err(WorryError::Eval {
+                monkey: 3,
+                error: EvalError::NotModular
+            })
+        );
+
+        let lcm: Monkeys = EXAMPLE
+            .replace("by 23", "by 6")
+            .replace("by 19", "by 4")
+            .parse()
+            .unwrap();
+        assert_eq!(lcm.lcm_test(), 12 * 13 * 17);
+    }
+
+    #[test]
+    fn parse_tolerates_whitespace_test() {
+        let messy = "\n  Monkey   0 :\nStarting items:\n Operation:   new = old  *  old\nTest: divisible by 2\nIf true: throw to monkey 0\nIf false: throw to monkey 0";
+        let monkeys: Monkeys = messy.parse().unwrap();
+
+        assert_eq!(monkeys.monkeys[0].holding.len(), 0);
+        assert_eq!(monkeys.monkeys[0].test, 2);
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        let err = EXAMPLE
+            .replace("throw to monkey 1\n", "throw to monkey 7\n")
+            .parse::<Monkeys>()
+            .unwrap_err();
+        assert_eq!(err.monkey(), Some(2));
+        assert_eq!(err.line(), 19);
+        assert_eq!(err.kind(), &ParseMonkeyErrorKind::UnknownTarget(7));
+
+        let err = EXAMPLE
+            .replace("Monkey 2:", "Monkey 5:")
+            .parse::<Monkeys>()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ParseMonkeyErrorKind::IdMismatch {
+                expected: 2,
+                found: 5
+            }
+        );
+        assert_eq!(err.line(), 15);
+
+        let err = EXAMPLE
+            .replace("old + 6", "old + (6")
+            .parse::<Monkeys>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "monkey 1, line 10: invalid operation: missing ')'"
+        );
+
+        let err = "Monkey 0:\n  Starting items: 1"
+            .parse::<Monkey>()
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ParseMonkeyErrorKind::MissingLine("Operation: new =")
+        );
     }
 }
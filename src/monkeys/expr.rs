@@ -0,0 +1,382 @@
+use std::{fmt::Display, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Old,
+    Constant(u128),
+    Binary(Box<Expr>, BinOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalError {
+    Overflow,
+    DivisionByZero,
+    // the expression can't be evaluated on residues, see `Expr::is_modular`
+    NotModular,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Overflow => write!(f, "worry level overflowed"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::NotModular => write!(f, "operation can't be evaluated on residues"),
+        }
+    }
+}
+
+impl Expr {
+    pub fn binary(l: Expr, op: BinOp, r: Expr) -> Self {
+        Self::Binary(Box::new(l), op, Box::new(r))
+    }
+
+    pub fn eval(&self, old: u128) -> Result<u128, EvalError> {
+        match self {
+            Expr::Old => Ok(old),
+            Expr::Constant(c) => Ok(*c),
+            Expr::Binary(l, op, r) => op.apply(l.eval(old)?, r.eval(old)?),
+        }
+    }
+
+    // whether the expression only uses +, -, * and constant exponents, which give the same residue
+    // whether they are applied to a worry level or to its residue
+    pub fn is_modular(&self) -> bool {
+        match self {
+            Expr::Old | Expr::Constant(_) => true,
+            Expr::Binary(l, BinOp::Pow, r) => l.is_modular() && matches!(**r, Expr::Constant(_)),
+            Expr::Binary(_, BinOp::Div | BinOp::Rem, _) => false,
+            Expr::Binary(l, _, r) => l.is_modular() && r.is_modular(),
+        }
+    }
+
+    // evaluates with `old` and every intermediate value reduced modulo `modulus`. Subtraction wraps
+    // around instead of failing, as the residue alone can't tell whether the plain value would
+    // have gone below zero.
+    pub fn eval_mod(&self, old: u128, modulus: u128) -> Result<u128, EvalError> {
+        match self {
+            Expr::Old => Ok(old % modulus),
+            Expr::Constant(c) => Ok(c % modulus),
+            Expr::Binary(l, BinOp::Pow, r) => match **r {
+                Expr::Constant(exp) => pow_mod(l.eval_mod(old, modulus)?, exp, modulus),
+                _ => Err(EvalError::NotModular),
+            },
+            Expr::Binary(l, op, r) => {
+                let (l, r) = (l.eval_mod(old, modulus)?, r.eval_mod(old, modulus)?);
+                let result = match op {
+                    BinOp::Add => l.checked_add(r),
+                    BinOp::Sub => l.checked_add(modulus - r),
+                    BinOp::Mul => l.checked_mul(r),
+                    _ => return Err(EvalError::NotModular),
+                };
+                Ok(result.ok_or(EvalError::Overflow)? % modulus)
+            }
+        }
+    }
+}
+
+fn pow_mod(mut base: u128, mut exp: u128, modulus: u128) -> Result<u128, EvalError> {
+    let mut result = 1 % modulus;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result.checked_mul(base).ok_or(EvalError::Overflow)? % modulus;
+        }
+        base = base.checked_mul(base).ok_or(EvalError::Overflow)? % modulus;
+        exp >>= 1;
+    }
+    Ok(result)
+}
+
+impl BinOp {
+    pub fn apply(self, l: u128, r: u128) -> Result<u128, EvalError> {
+        let result = match self {
+            BinOp::Add => l.checked_add(r),
+            BinOp::Sub => l.checked_sub(r),
+            BinOp::Mul => l.checked_mul(r),
+            BinOp::Div | BinOp::Rem if r == 0 => return Err(EvalError::DivisionByZero),
+            BinOp::Div => Some(l / r),
+            BinOp::Rem => Some(l % r),
+            BinOp::Pow => match u32::try_from(r) {
+                Ok(r) => l.checked_pow(r),
+                // 0 and 1 are the only bases that survive such a large exponent
+                Err(_) if l <= 1 => Some(l),
+                Err(_) => None,
+            },
+        };
+        result.ok_or(EvalError::Overflow)
+    }
+
+    // higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Add | BinOp::Sub => 1,
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 2,
+            BinOp::Pow => 3,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            BinOp::Add => '+',
+            BinOp::Sub => '-',
+            BinOp::Mul => '*',
+            BinOp::Div => '/',
+            BinOp::Rem => '%',
+            BinOp::Pow => '^',
+        }
+    }
+
+    fn from_symbol(c: char) -> Option<Self> {
+        match c {
+            '+' => Some(BinOp::Add),
+            '-' => Some(BinOp::Sub),
+            '*' => Some(BinOp::Mul),
+            '/' => Some(BinOp::Div),
+            '%' => Some(BinOp::Rem),
+            '^' => Some(BinOp::Pow),
+            _ => None,
+        }
+    }
+}
+
+// only parenthesises where the precedence requires it, so the output parses back to the same tree
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Constant(c) => write!(f, "{}", c),
+            Expr::Binary(l, op, r) => {
+                // `^` is right associative, everything else is left associative
+                let (l_min, r_min) = match op {
+                    BinOp::Pow => (op.precedence() + 1, op.precedence()),
+                    _ => (op.precedence(), op.precedence() + 1),
+                };
+                write_operand(f, l, l_min)?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, r, r_min)
+            }
+        }
+    }
+}
+
+fn write_operand(
+    f: &mut std::fmt::Formatter<'_>,
+    e: &Expr,
+    min_precedence: u8,
+) -> std::fmt::Result {
+    match e {
+        Expr::Binary(_, op, _) if op.precedence() < min_precedence => write!(f, "({})", e),
+        _ => write!(f, "{}", e),
+    }
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.expr(1)?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Old,
+    Number(u128),
+    Op(BinOp),
+    Open,
+    Close,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Old => write!(f, "old"),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Op(op) => write!(f, "{}", op.symbol()),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if c.is_ascii_digit() => {
+                let mut end = start + 1;
+                while let Some((i, _)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = i + 1;
+                }
+                let n = s[start..end]
+                    .parse()
+                    .map_err(|_| format!("number '{}' is too large", &s[start..end]))?;
+                Token::Number(n)
+            }
+            'o' if s[start..].starts_with("old") => {
+                chars.nth(1);
+                Token::Old
+            }
+            c => match BinOp::from_symbol(c) {
+                Some(op) => Token::Op(op),
+                None => return Err(format!("unexpected '{}' at {}", c, start)),
+            },
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    // precedence climbing: parses operators that bind at least as tight as `min_precedence`
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, String> {
+        let mut l = self.atom()?;
+        while let Some(Token::Op(op)) = self.tokens.get(self.pos).copied() {
+            if op.precedence() < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let next_min = match op {
+                BinOp::Pow => op.precedence(),
+                _ => op.precedence() + 1,
+            };
+            let r = self.expr(next_min)?;
+            l = Expr::binary(l, op, r);
+        }
+        Ok(l)
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        match token {
+            Some(Token::Old) => Ok(Expr::Old),
+            Some(Token::Number(n)) => Ok(Expr::Constant(n)),
+            Some(Token::Open) => {
+                let e = self.expr(1)?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(e)
+                    }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected '{}'", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_eval_test() {
+        let e: Expr = "old * old".parse().unwrap();
+        assert_eq!(e, Expr::binary(Expr::Old, BinOp::Mul, Expr::Old));
+        assert_eq!(e.eval(7), Ok(49));
+
+        let e: Expr = "(old + 3) * 2 - old % 4 / 2".parse().unwrap();
+        assert_eq!(e.eval(7), Ok(19));
+        assert_eq!("2 ^ 3 ^ 2".parse::<Expr>().unwrap().eval(0), Ok(512));
+        assert_eq!("10 - 4 - 3".parse::<Expr>().unwrap().eval(0), Ok(3));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for s in [
+            "old * 19",
+            "(old + 3) * 2",
+            "old - (3 - old)",
+            "(2 ^ 3) ^ old",
+        ] {
+            let e: Expr = s.parse().unwrap();
+            assert_eq!(e.to_string(), s);
+            assert_eq!(e.to_string().parse::<Expr>().unwrap(), e);
+        }
+        let e: Expr = "((old)) * (2 * 3)".parse().unwrap();
+        assert_eq!(e.to_string(), "old * (2 * 3)");
+    }
+
+    #[test]
+    fn eval_errors_test() {
+        let e: Expr = "old * old".parse().unwrap();
+        assert_eq!(e.eval(u128::MAX), Err(EvalError::Overflow));
+        assert_eq!(
+            "3 - old".parse::<Expr>().unwrap().eval(4),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(
+            "old % (old - 2)".parse::<Expr>().unwrap().eval(2),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!("old ^ 300".parse::<Expr>().unwrap().eval(1), Ok(1));
+    }
+
+    #[test]
+    fn eval_mod_test() {
+        for s in [
+            "old * old + 3",
+            "(old - 5) * 7",
+            "old ^ 5 + 2 * old",
+            "3 ^ 50",
+        ] {
+            let e: Expr = s.parse().unwrap();
+            assert!(e.is_modular());
+            for old in [5, 12, 999] {
+                let plain = e.eval(old).unwrap();
+                for modulus in [1, 2, 13, 23] {
+                    assert_eq!(
+                        e.eval_mod(old, modulus),
+                        Ok(plain % modulus),
+                        "{} {}",
+                        s,
+                        old
+                    );
+                }
+            }
+        }
+
+        let e: Expr = "old / 3".parse().unwrap();
+        assert!(!e.is_modular());
+        assert_eq!(e.eval_mod(9, 7), Err(EvalError::NotModular));
+        assert!(!"2 ^ old".parse::<Expr>().unwrap().is_modular());
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        assert!("old +".parse::<Expr>().is_err());
+        assert!("(old + 1".parse::<Expr>().is_err());
+        assert!("old old".parse::<Expr>().is_err());
+        assert!("new * 2".parse::<Expr>().is_err());
+        assert!("".parse::<Expr>().is_err());
+    }
+}
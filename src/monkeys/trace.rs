@@ -0,0 +1,137 @@
+#[cfg(feature = "serde")]
+use std::io::{self, Write};
+
+use super::{Monkeys, Worry};
+
+// one monkey inspecting one item
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Inspection {
+    round: usize,
+    monkey: usize,
+    worry: Worry,
+    new_worry: Worry,
+    divisible: bool,
+    target: usize,
+}
+
+impl Inspection {
+    pub(super) fn new(
+        round: usize,
+        monkey: usize,
+        worry: Worry,
+        new_worry: Worry,
+        divisible: bool,
+        target: usize,
+    ) -> Self {
+        Self {
+            round,
+            monkey,
+            worry,
+            new_worry,
+            divisible,
+            target,
+        }
+    }
+
+    // rounds are counted from 1
+    pub fn round(&self) -> usize {
+        self.round
+    }
+
+    pub fn monkey(&self) -> usize {
+        self.monkey
+    }
+
+    pub fn worry(&self) -> &Worry {
+        &self.worry
+    }
+
+    // after the operation and any relief
+    pub fn new_worry(&self) -> &Worry {
+        &self.new_worry
+    }
+
+    pub fn divisible(&self) -> bool {
+        self.divisible
+    }
+
+    pub fn target(&self) -> usize {
+        self.target
+    }
+}
+
+pub trait MonkeyObserver {
+    // inspections are only recorded for observers that want them, since that copies every worry
+    fn wants_inspections(&self) -> bool {
+        true
+    }
+
+    // called for every inspection of a round once the whole round has succeeded
+    fn on_inspection(&mut self, inspection: &Inspection) {}
+
+    fn on_round_end(&mut self, monkeys: &Monkeys) {}
+}
+
+impl<F: FnMut(&Inspection)> MonkeyObserver for F {
+    fn on_inspection(&mut self, inspection: &Inspection) {
+        self(inspection)
+    }
+}
+
+pub struct Silent;
+
+impl MonkeyObserver for Silent {
+    fn wants_inspections(&self) -> bool {
+        false
+    }
+}
+
+// the items each monkey holds after every round
+#[derive(Default)]
+pub struct SnapshotRecorder {
+    snapshots: Vec<Vec<Vec<Worry>>>,
+}
+
+impl SnapshotRecorder {
+    pub fn snapshots(&self) -> &[Vec<Vec<Worry>>] {
+        &self.snapshots
+    }
+}
+
+impl MonkeyObserver for SnapshotRecorder {
+    fn wants_inspections(&self) -> bool {
+        false
+    }
+
+    fn on_round_end(&mut self, monkeys: &Monkeys) {
+        self.snapshots.push(monkeys.snapshot());
+    }
+}
+
+#[derive(Default)]
+pub struct EventLog {
+    inspections: Vec<Inspection>,
+}
+
+impl EventLog {
+    pub fn inspections(&self) -> &[Inspection] {
+        &self.inspections
+    }
+
+    // one JSON object per inspection and line; residues are written as arrays
+    #[cfg(feature = "serde")]
+    pub fn write_json_lines<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for inspection in &self.inspections {
+            serde_json::to_writer(&mut *writer, inspection)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl MonkeyObserver for EventLog {
+    fn on_inspection(&mut self, inspection: &Inspection) {
+        self.inspections.push(inspection.clone());
+    }
+}
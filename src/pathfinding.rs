@@ -0,0 +1,291 @@
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::Entry, BinaryHeap, HashMap, VecDeque},
+    hash::Hash,
+};
+
+use simple_grid::GridIndex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<N = GridIndex> {
+    nodes: Vec<N>,
+    cost: u32,
+}
+
+impl<N> Path<N> {
+    // from the start to the goal, both included
+    pub fn nodes(&self) -> &[N] {
+        &self.nodes
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.nodes.len() as u32 - 1
+    }
+
+    // the sum of the step costs, which is `steps` for unweighted searches
+    pub fn cost(&self) -> u32 {
+        self.cost
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.nodes.reverse();
+        self
+    }
+}
+
+pub fn bfs<N, I>(
+    start: N,
+    mut neighbors: impl FnMut(N) -> I,
+    mut is_goal: impl FnMut(N) -> bool,
+) -> Option<Path<N>>
+where
+    N: Copy + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut came_from = HashMap::new();
+    let mut to_visit = VecDeque::new();
+    came_from.insert(start, None);
+    to_visit.push_back(start);
+
+    while let Some(current) = to_visit.pop_front() {
+        if is_goal(current) {
+            return Some(reconstruct(&came_from, current, None));
+        }
+
+        for n in neighbors(current) {
+            if let Entry::Vacant(entry) = came_from.entry(n) {
+                entry.insert(Some(current));
+                to_visit.push_back(n);
+            }
+        }
+    }
+
+    None
+}
+
+// the fewest steps from the closest source to every node that can be reached
+pub fn distances<N, I>(
+    sources: impl IntoIterator<Item = N>,
+    mut neighbors: impl FnMut(N) -> I,
+) -> HashMap<N, u32>
+where
+    N: Copy + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut distances = HashMap::new();
+    let mut to_visit = VecDeque::new();
+    for source in sources {
+        if let Entry::Vacant(entry) = distances.entry(source) {
+            entry.insert(0);
+            to_visit.push_back(source);
+        }
+    }
+
+    while let Some(current) = to_visit.pop_front() {
+        let steps = distances[&current] + 1;
+        for n in neighbors(current) {
+            if let Entry::Vacant(entry) = distances.entry(n) {
+                entry.insert(steps);
+                to_visit.push_back(n);
+            }
+        }
+    }
+
+    distances
+}
+
+// `neighbors` yields each neighbour with the cost of stepping onto it
+pub fn dijkstra<N, I>(
+    start: N,
+    neighbors: impl FnMut(N) -> I,
+    is_goal: impl FnMut(N) -> bool,
+) -> Option<Path<N>>
+where
+    N: Copy + Eq + Hash,
+    I: IntoIterator<Item = (N, u32)>,
+{
+    astar(start, neighbors, is_goal, |_| 0)
+}
+
+// `heuristic` must never overestimate the remaining cost, or the path found may not be the cheapest
+pub fn astar<N, I>(
+    start: N,
+    mut neighbors: impl FnMut(N) -> I,
+    mut is_goal: impl FnMut(N) -> bool,
+    mut heuristic: impl FnMut(N) -> u32,
+) -> Option<Path<N>>
+where
+    N: Copy + Eq + Hash,
+    I: IntoIterator<Item = (N, u32)>,
+{
+    let mut came_from = HashMap::new();
+    let mut costs = HashMap::new();
+    let mut to_visit = BinaryHeap::new();
+    came_from.insert(start, None);
+    costs.insert(start, 0);
+    to_visit.push(Queued {
+        priority: heuristic(start),
+        cost: 0,
+        node: start,
+    });
+
+    while let Some(Queued { cost, node, .. }) = to_visit.pop() {
+        // a cheaper way here was found after this entry was queued
+        if costs[&node] < cost {
+            continue;
+        }
+        if is_goal(node) {
+            return Some(reconstruct(&came_from, node, Some(cost)));
+        }
+
+        for (n, step_cost) in neighbors(node) {
+            let n_cost = cost + step_cost;
+            if costs.get(&n).is_none_or(|c| n_cost < *c) {
+                costs.insert(n, n_cost);
+                came_from.insert(n, Some(node));
+                to_visit.push(Queued {
+                    priority: n_cost + heuristic(n),
+                    cost: n_cost,
+                    node: n,
+                });
+            }
+        }
+    }
+
+    None
+}
+
+pub fn manhattan(a: GridIndex, b: GridIndex) -> u32 {
+    (a.column().abs_diff(b.column()) + a.row().abs_diff(b.row())) as u32
+}
+
+// the fewest steps when diagonal steps are allowed
+pub fn chebyshev(a: GridIndex, b: GridIndex) -> u32 {
+    a.column()
+        .abs_diff(b.column())
+        .max(a.row().abs_diff(b.row())) as u32
+}
+
+fn reconstruct<N: Copy + Eq + Hash>(
+    came_from: &HashMap<N, Option<N>>,
+    goal: N,
+    cost: Option<u32>,
+) -> Path<N> {
+    let mut nodes = vec![goal];
+    while let Some(previous) = came_from[nodes.last().unwrap()] {
+        nodes.push(previous);
+    }
+    nodes.reverse();
+    let cost = cost.unwrap_or(nodes.len() as u32 - 1);
+    Path { nodes, cost }
+}
+
+// ordered so that the max-heap pops the lowest priority first
+struct Queued<N> {
+    priority: u32,
+    cost: u32,
+    node: N,
+}
+
+impl<N> PartialEq for Queued<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<N> Eq for Queued<N> {}
+
+impl<N> PartialOrd for Queued<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N> Ord for Queued<N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // among equal priorities, prefer the entry that got further
+        other
+            .priority
+            .cmp(&self.priority)
+            .then(self.cost.cmp(&other.cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 5x5 open grid with a wall down column 2, except at the bottom row
+    fn grid_neighbors(idx: GridIndex) -> Vec<GridIndex> {
+        let (col, row) = (idx.column() as isize, idx.row() as isize);
+        [(0, -1), (0, 1), (-1, 0), (1, 0)]
+            .into_iter()
+            .map(|(dc, dr)| (col + dc, row + dr))
+            .filter(|(c, r)| (0..5).contains(c) && (0..5).contains(r))
+            .filter(|(c, r)| *c != 2 || *r == 4)
+            .map(|(c, r)| GridIndex::new(c as usize, r as usize))
+            .collect()
+    }
+
+    #[test]
+    fn bfs_test() {
+        let goal = GridIndex::new(4, 0);
+        let path = bfs(GridIndex::new(0, 0), grid_neighbors, |n| n == goal).unwrap();
+
+        assert_eq!(path.steps(), 12);
+        assert_eq!(path.cost(), 12);
+        assert_eq!(path.nodes()[0], GridIndex::new(0, 0));
+        assert_eq!(path.nodes()[12], goal);
+        assert!(path.nodes().contains(&GridIndex::new(2, 4)));
+        assert!(path.nodes().windows(2).all(|w| manhattan(w[0], w[1]) == 1));
+
+        assert_eq!(
+            bfs(0, |n: i32| [n + 1], |n| n == 3).unwrap().nodes(),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(bfs(0, |n: i32| [(n + 1) % 3], |n| n == 5), None);
+    }
+
+    #[test]
+    fn distances_test() {
+        let distances = distances([GridIndex::new(0, 0), GridIndex::new(4, 4)], grid_neighbors);
+
+        assert_eq!(distances.len(), 25 - 4);
+        assert_eq!(distances[&GridIndex::new(0, 0)], 0);
+        assert_eq!(distances[&GridIndex::new(1, 1)], 2);
+        assert_eq!(distances[&GridIndex::new(4, 0)], 4);
+        assert_eq!(distances.get(&GridIndex::new(2, 0)), None);
+    }
+
+    #[test]
+    fn weighted_test() {
+        let goal = GridIndex::new(4, 0);
+        // stepping onto the bottom row is expensive, but it is the only way through
+        let weighted = |idx| {
+            grid_neighbors(idx)
+                .into_iter()
+                .map(|n| (n, if n.row() == 4 { 5 } else { 1 }))
+        };
+        let by_dijkstra = dijkstra(GridIndex::new(0, 0), weighted, |n| n == goal).unwrap();
+        let by_astar = astar(
+            GridIndex::new(0, 0),
+            weighted,
+            |n| n == goal,
+            |n| manhattan(n, goal),
+        )
+        .unwrap();
+
+        assert_eq!(by_dijkstra.steps(), 12);
+        assert_eq!(by_dijkstra.cost(), 8 + 3 * 5 + 1);
+        assert_eq!(by_astar.cost(), by_dijkstra.cost());
+
+        // a detour is cheaper than the direct edge
+        let edges = |n: u8| match n {
+            0 => vec![(1, 10), (2, 1)],
+            2 => vec![(1, 1)],
+            _ => vec![],
+        };
+        let path = dijkstra(0, edges, |n| n == 1).unwrap();
+        assert_eq!((path.nodes(), path.cost()), (&[0, 2, 1][..], 2));
+    }
+}
@@ -1,212 +1,813 @@
-use std::{collections::HashSet, fmt::Display, str::FromStr};
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
 
-pub struct RopeSim {
-    positions: Vec<Idx>,
+use crate::heatmap::{self, Rgb};
+use simple_grid::Grid;
+
+pub struct RopeSim<const D: usize = 2> {
+    positions: Vec<Idx<D>>,
 }
 
-impl RopeSim {
-    pub fn new(positions: Vec<Idx>) -> Self {
+impl<const D: usize> RopeSim<D> {
+    pub fn new(positions: Vec<Idx<D>>) -> Self {
         if positions.is_empty() {
             panic!()
         }
         Self { positions }
     }
 
-    fn tail_pos(&self) -> Idx {
-        self.positions[self.positions.len() - 1]
+    pub fn positions(&self) -> &[Idx<D>] {
+        &self.positions
     }
 
-    pub fn tail_visits(&mut self, motions: &[Motion]) -> usize {
-        let mut visited = HashSet::new();
-        visited.insert(self.tail_pos());
+    pub fn tail_visits(&mut self, motions: &[Motion<D>]) -> usize {
+        self.run(motions).tail_visits()
+    }
 
+    pub fn run(&mut self, motions: &[Motion<D>]) -> RopeReport<D> {
+        let mut recorder = ReportRecorder::default();
+        self.run_with(motions, &mut recorder);
+        RopeReport {
+            visited: recorder.visits.visited,
+            distances: recorder.distances,
+            bounds: recorder.bounds.unwrap(),
+            final_positions: self.positions.clone(),
+        }
+    }
+
+    pub fn run_with<O: RopeObserver<D>>(&mut self, motions: &[Motion<D>], observer: &mut O) {
+        observer.on_start(&self.positions);
         for motion in motions {
-            let step_diff = motion.direction.step_diff();
             for step in 0..motion.steps {
-                for i in 0..self.positions.len() {
-                    let pos = self.positions[i];
-                    if i == 0 {
-                        // head
-                        self.positions[0] = pos.apply_step_diff(step_diff);
-                    } else {
-                        let in_front = self.positions[i - 1];
-                        let new_pos = pos.move_towards(in_front);
-                        self.positions[i] = new_pos;
-                    }
-                }
-                visited.insert(self.tail_pos());
+                self.step(motion.step);
+                observer.on_step(motion.step, &self.positions);
             }
         }
-        Self::tail_display(&visited);
-        visited.len()
     }
 
-    fn big_display(&self) {
+    fn step(&mut self, step_diff: Idx<D>) {
+        for i in 0..self.positions.len() {
+            let pos = self.positions[i];
+            if i == 0 {
+                // head
+                self.positions[0] = pos.apply_step_diff(step_diff);
+            } else {
+                let in_front = self.positions[i - 1];
+                let new_pos = pos.move_towards(in_front);
+                self.positions[i] = new_pos;
+            }
+        }
+    }
+}
+
+pub trait RopeObserver<const D: usize = 2> {
+    fn on_start(&mut self, knots: &[Idx<D>]) {}
+
+    fn on_step(&mut self, head_move: Idx<D>, knots: &[Idx<D>]);
+}
+
+impl<const D: usize, F: FnMut(Idx<D>, &[Idx<D>])> RopeObserver<D> for F {
+    fn on_step(&mut self, head_move: Idx<D>, knots: &[Idx<D>]) {
+        self(head_move, knots)
+    }
+}
+
+pub struct Silent;
+
+impl<const D: usize> RopeObserver<D> for Silent {
+    fn on_step(&mut self, _head_move: Idx<D>, _knots: &[Idx<D>]) {}
+}
+
+#[derive(Default)]
+pub struct VisitRecorder<const D: usize = 2> {
+    visited: Vec<HashSet<Idx<D>>>,
+}
+
+impl<const D: usize> VisitRecorder<D> {
+    pub fn visited(&self, knot: usize) -> &HashSet<Idx<D>> {
+        &self.visited[knot]
+    }
+
+    fn record(&mut self, knots: &[Idx<D>]) {
+        self.visited.resize_with(knots.len(), HashSet::new);
+        for (visited, knot) in self.visited.iter_mut().zip(knots) {
+            visited.insert(*knot);
+        }
+    }
+}
+
+impl<const D: usize> RopeObserver<D> for VisitRecorder<D> {
+    fn on_start(&mut self, knots: &[Idx<D>]) {
+        self.record(knots);
+    }
+
+    fn on_step(&mut self, _head_move: Idx<D>, knots: &[Idx<D>]) {
+        self.record(knots);
+    }
+}
+
+#[derive(Default)]
+struct ReportRecorder<const D: usize> {
+    visits: VisitRecorder<D>,
+    previous: Vec<Idx<D>>,
+    distances: Vec<usize>,
+    bounds: Option<Bounds<D>>,
+}
+
+impl<const D: usize> ReportRecorder<D> {
+    fn include(&mut self, knots: &[Idx<D>]) {
+        let around = Bounds::around(knots);
+        self.bounds = match (self.bounds, around) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+impl<const D: usize> RopeObserver<D> for ReportRecorder<D> {
+    fn on_start(&mut self, knots: &[Idx<D>]) {
+        self.visits.on_start(knots);
+        self.previous = knots.to_vec();
+        self.distances = vec![0; knots.len()];
+        self.include(knots);
+    }
+
+    fn on_step(&mut self, head_move: Idx<D>, knots: &[Idx<D>]) {
+        self.visits.on_step(head_move, knots);
+        for ((distance, previous), knot) in self.distances.iter_mut().zip(&self.previous).zip(knots)
+        {
+            *distance += previous.distance_to(*knot);
+        }
+        self.previous.copy_from_slice(knots);
+        self.include(knots);
+    }
+}
+
+pub struct RopeReport<const D: usize = 2> {
+    visited: Vec<HashSet<Idx<D>>>,
+    distances: Vec<usize>,
+    bounds: Bounds<D>,
+    final_positions: Vec<Idx<D>>,
+}
+
+impl<const D: usize> RopeReport<D> {
+    pub fn knot_count(&self) -> usize {
+        self.final_positions.len()
+    }
+
+    pub fn visited(&self, knot: usize) -> &HashSet<Idx<D>> {
+        &self.visited[knot]
+    }
+
+    pub fn tail_visits(&self) -> usize {
+        self.visited(self.knot_count() - 1).len()
+    }
+
+    // chebyshev distance summed over every step, so a diagonal move counts as one
+    pub fn distance_moved(&self, knot: usize) -> usize {
+        self.distances[knot]
+    }
+
+    pub fn bounds(&self) -> Bounds<D> {
+        self.bounds
+    }
+
+    pub fn final_positions(&self) -> &[Idx<D>] {
+        &self.final_positions
+    }
+}
+
+// keeps every frame so that the bounds can cover the whole run before anything is drawn
+#[derive(Default)]
+pub struct FrameRecorder<const D: usize = 2> {
+    frames: Vec<Vec<Idx<D>>>,
+}
+
+impl<const D: usize> FrameRecorder<D> {
+    pub fn frames(&self) -> &[Vec<Idx<D>>] {
+        &self.frames
+    }
+
+    pub fn bounds(&self) -> Option<Bounds<D>> {
+        Bounds::around(self.frames.iter().flatten())
+    }
+}
+
+impl FrameRecorder<2> {
+    // `None` if there is no such frame, e.g. because the recorder never watched a run
+    pub fn render(&self, frame: usize) -> Option<String> {
+        let bounds = self.bounds()?;
+        let knots = self.frames.get(frame)?;
         let mut s = String::new();
-        for row in (-15..=15) {
-            for col in (-20..=15) {
-                let idx = Idx::new(col, row);
-                'find: {
-                    for (i, pos) in self.positions.iter().enumerate() {
-                        if *pos == idx {
-                            s.push_str(&i.to_string());
-                            break 'find;
-                        }
-                    }
-                    s.push('.');
+        for row in bounds.min.row()..=bounds.max.row() {
+            for col in bounds.min.column()..=bounds.max.column() {
+                match knots.iter().position(|k| *k == Idx::new(col, row)) {
+                    Some(i) => s.push_str(&i.to_string()),
+                    None => s.push('.'),
                 }
             }
             s.push('\n');
         }
-        println!("{}", s);
+        Some(s)
     }
 
-    fn tail_display(positions: &HashSet<Idx>) {
-        let mut s = String::new();
-        for row in (-15..=15) {
-            for col in (-15..=15) {
-                match positions.contains(&Idx::new(col, row)) {
-                    true => s.push('#'),
-                    false => s.push('.'),
+    pub fn render_image(&self, frame: usize) -> Option<Grid<Rgb>> {
+        let bounds = self.bounds()?;
+        self.frames.get(frame)?;
+        let tail = self.frames[0].len() - 1;
+        let mut image = blank_image(&bounds);
+        for knots in &self.frames[..=frame] {
+            fill_cell(&mut image, &bounds, knots[tail], TRAIL);
+        }
+        self.draw_knots(&mut image, &bounds, frame);
+        Some(image)
+    }
+
+    // writes frame_00000.ppm, frame_00001.ppm, ... into `dir` and returns how many were written
+    pub fn write_ppm_frames(&self, dir: impl AsRef<Path>, stride: usize) -> io::Result<usize> {
+        let bounds = self.export_bounds()?;
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut written = 0;
+        self.for_each_image(&bounds, stride, |image| {
+            let path = dir.join(format!("frame_{:05}.ppm", written));
+            let mut writer = BufWriter::new(File::create(path)?);
+            heatmap::write_ppm(&mut writer, &image)?;
+            writer.flush()?;
+            written += 1;
+            Ok(())
+        })?;
+        Ok(written)
+    }
+
+    // `delay` is in hundredths of a second, as in the gif format
+    #[cfg(feature = "gif")]
+    pub fn write_gif<W: Write>(&self, writer: W, stride: usize, delay: u16) -> io::Result<()> {
+        let bounds = self.export_bounds()?;
+        let (width, height) = (bounds.width() * CELL_SIZE, bounds.height() * CELL_SIZE);
+        let too_large = |_| io::Error::new(io::ErrorKind::InvalidInput, "animation is too large");
+        let width = u16::try_from(width).map_err(too_large)?;
+        let height = u16::try_from(height).map_err(too_large)?;
+
+        let mut encoder =
+            gif::Encoder::new(writer, width, height, &[]).map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        self.for_each_image(&bounds, stride, |image| {
+            let data: Vec<u8> = image
+                .cell_iter()
+                .flat_map(|Rgb(r, g, b)| [*r, *g, *b])
+                .collect();
+            let mut frame = gif::Frame::from_rgb_speed(width, height, &data, 10);
+            frame.delay = delay;
+            encoder.write_frame(&frame).map_err(io::Error::other)
+        })
+    }
+
+    fn export_bounds(&self) -> io::Result<Bounds> {
+        self.bounds().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no frames have been recorded")
+        })
+    }
+
+    // every `stride`th frame, always ending with the last one. The trail is painted onto a
+    // background as it grows, so each frame only has to draw the knots on a copy of it
+    fn for_each_image<F>(&self, bounds: &Bounds, stride: usize, mut f: F) -> io::Result<()>
+    where
+        F: FnMut(Grid<Rgb>) -> io::Result<()>,
+    {
+        let stride = stride.max(1);
+        let tail = self.frames[0].len() - 1;
+        let last = self.frames.len() - 1;
+        let mut background = blank_image(bounds);
+        for (i, knots) in self.frames.iter().enumerate() {
+            fill_cell(&mut background, bounds, knots[tail], TRAIL);
+            if i % stride == 0 || i == last {
+                let mut image = background.clone();
+                self.draw_knots(&mut image, bounds, i);
+                f(image)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_knots(&self, image: &mut Grid<Rgb>, bounds: &Bounds, frame: usize) {
+        // back to front, so that the head ends up on top like in `render`
+        let knots = &self.frames[frame];
+        for (i, knot) in knots.iter().enumerate().rev() {
+            let colour = if i == 0 {
+                Rgb::RED
+            } else {
+                Rgb::gradient(i as u32, knots.len() as u32)
+            };
+            let (x, y) = fill_cell(image, bounds, *knot, colour);
+            if let Some(glyph) = DIGITS.get(i) {
+                for (gy, line) in glyph.iter().enumerate() {
+                    for (gx, pixel) in line.chars().enumerate() {
+                        if pixel == '#' {
+                            image[(x + 2 + gx, y + 1 + gy)] = Rgb::BLACK;
+                        }
+                    }
                 }
             }
-            s.push('\n');
         }
-        println!("{}", s);
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
-pub struct Idx {
-    column: isize,
-    row: isize,
+const CELL_SIZE: usize = 7;
+const BACKGROUND: Rgb = Rgb(32, 32, 32);
+const TRAIL: Rgb = Rgb(40, 110, 60);
+
+// 3x5 glyphs for labelling knots, drawn inside a cell with a one pixel margin
+const DIGITS: [[&str; 5]; 10] = [
+    ["###", "#.#", "#.#", "#.#", "###"],
+    [".#.", "##.", ".#.", ".#.", "###"],
+    ["###", "..#", "###", "#..", "###"],
+    ["###", "..#", "###", "..#", "###"],
+    ["#.#", "#.#", "###", "..#", "..#"],
+    ["###", "#..", "###", "..#", "###"],
+    ["###", "#..", "###", "#.#", "###"],
+    ["###", "..#", "..#", "..#", "..#"],
+    ["###", "#.#", "###", "#.#", "###"],
+    ["###", "#.#", "###", "..#", "###"],
+];
+
+fn blank_image(bounds: &Bounds) -> Grid<Rgb> {
+    let (width, height) = (bounds.width() * CELL_SIZE, bounds.height() * CELL_SIZE);
+    Grid::new(width, height, vec![BACKGROUND; width * height])
+}
+
+// returns the top left pixel of the cell
+fn fill_cell(image: &mut Grid<Rgb>, bounds: &Bounds, idx: Idx, colour: Rgb) -> (usize, usize) {
+    let x = (idx.column() - bounds.min.column()) as usize * CELL_SIZE;
+    let y = (idx.row() - bounds.min.row()) as usize * CELL_SIZE;
+    for dy in 0..CELL_SIZE - 1 {
+        for dx in 0..CELL_SIZE - 1 {
+            image[(x + dx, y + dy)] = colour;
+        }
+    }
+    (x, y)
+}
+
+impl<const D: usize> RopeObserver<D> for FrameRecorder<D> {
+    fn on_start(&mut self, knots: &[Idx<D>]) {
+        self.frames.push(knots.to_vec());
+    }
+
+    fn on_step(&mut self, _head_move: Idx<D>, knots: &[Idx<D>]) {
+        self.frames.push(knots.to_vec());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds<const D: usize = 2> {
+    min: Idx<D>,
+    max: Idx<D>,
 }
 
-impl Idx {
+impl<const D: usize> Bounds<D> {
+    pub fn around<'a>(positions: impl IntoIterator<Item = &'a Idx<D>>) -> Option<Self> {
+        let mut positions = positions.into_iter();
+        let first = *positions.next()?;
+        let mut bounds = Self {
+            min: first,
+            max: first,
+        };
+        for pos in positions {
+            for axis in 0..D {
+                bounds.min.0[axis] = bounds.min.0[axis].min(pos.0[axis]);
+                bounds.max.0[axis] = bounds.max.0[axis].max(pos.0[axis]);
+            }
+        }
+        Some(bounds)
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self::around(&[self.min, self.max, other.min, other.max]).unwrap()
+    }
+
+    pub fn min(&self) -> Idx<D> {
+        self.min
+    }
+
+    pub fn max(&self) -> Idx<D> {
+        self.max
+    }
+
+    pub fn extent(&self, axis: usize) -> usize {
+        self.max.0[axis].abs_diff(self.min.0[axis]) + 1
+    }
+}
+
+impl Bounds<2> {
+    pub fn width(&self) -> usize {
+        self.extent(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.extent(1)
+    }
+}
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Idx<const D: usize = 2>([isize; D]);
+
+impl Idx<2> {
     pub fn new(column: isize, row: isize) -> Self {
-        Self { column, row }
+        Self([column, row])
     }
 
+    pub fn column(&self) -> isize {
+        self.0[0]
+    }
+
+    pub fn row(&self) -> isize {
+        self.0[1]
+    }
+}
+
+impl Idx<3> {
+    pub fn new_3d(x: isize, y: isize, z: isize) -> Self {
+        Self([x, y, z])
+    }
+}
+
+impl<const D: usize> Idx<D> {
+    pub fn from_coords(coords: [isize; D]) -> Self {
+        Self(coords)
+    }
+
+    pub fn coords(&self) -> [isize; D] {
+        self.0
+    }
+
+    // one step along every axis where the target differs, so gaps of any size are closed
+    // gradually instead of being rejected
     fn move_towards(&self, target: Self) -> Self {
         if Self::are_touching(*self, target) {
             *self
         } else {
-            let col_diff = target.column - self.column;
-            let row_diff = target.row - self.row;
-
-            match (col_diff.abs(), row_diff.abs()) {
-                (0, 2) => {
-                    // move vertically 1 step
-                    self.apply_step_diff((0, row_diff.signum()))
-                }
-                (2, 0) => {
-                    // move horizontally 1 step
-                    self.apply_step_diff((col_diff.signum(), 0))
-                }
-                (1, 2) | (2, 1) | (2, 2) => {
-                    // move diagonally towards the target
-                    self.apply_step_diff((col_diff.signum(), row_diff.signum()))
-                }
-                invalid => {
-                    panic!(
-                        "invalid diff: {:?} between {} and {}",
-                        invalid, self, target
-                    )
-                }
+            let mut diff = [0; D];
+            for (axis, d) in diff.iter_mut().enumerate() {
+                *d = (target.0[axis] - self.0[axis]).signum();
             }
+            self.apply_step_diff(Self(diff))
         }
     }
 
+    // chebyshev distance, since a diagonal move counts as a single step
+    pub fn distance_to(&self, other: Self) -> usize {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0)
+    }
+
     fn are_touching(a: Self, b: Self) -> bool {
-        let col_diff = a.column.abs_diff(b.column);
-        let row_diff = a.row.abs_diff(b.row);
-        // diagonal special case
-        (col_diff == 0 || col_diff == 1) && (row_diff == 0 || row_diff == 1)
+        // diagonals count as touching, in any number of dimensions
+        a.distance_to(b) <= 1
     }
 
-    fn apply_step_diff(&self, diff: (isize, isize)) -> Self {
-        Self::new(self.column + diff.0, self.row + diff.1)
+    fn apply_step_diff(&self, diff: Self) -> Self {
+        let mut coords = self.0;
+        for (c, d) in coords.iter_mut().zip(diff.0) {
+            *c += d;
+        }
+        Self(coords)
     }
 }
 
-impl Display for Idx {
+impl<const D: usize> Display for Idx<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({},{})", self.column, self.row)
+        let coords: Vec<_> = self.0.iter().map(|c| c.to_string()).collect();
+        write!(f, "({})", coords.join(","))
     }
 }
 
-pub struct Motion {
-    direction: Direction,
+pub struct Motion<const D: usize = 2> {
+    step: Idx<D>,
     steps: usize,
 }
 
-impl Motion {
-    fn new(direction: Direction, steps: usize) -> Self {
-        Self { direction, steps }
+impl Motion<2> {
+    pub fn new(direction: Direction, steps: usize) -> Self {
+        Self::from_step(direction.step_diff(), steps)
+    }
+}
+
+impl<const D: usize> Motion<D> {
+    pub fn from_step(step: Idx<D>, steps: usize) -> Self {
+        Self { step, steps }
+    }
+}
+
+// letters for the negative and positive direction along each axis
+const AXIS_LETTERS: [(char, char); 3] = [('L', 'R'), ('U', 'D'), ('F', 'B')];
+
+// one letter per axis moved along, in any order, so "UL" and "LU" are the same diagonal
+fn parse_step<const D: usize>(letters: &str) -> Result<Idx<D>, ()> {
+    if letters.is_empty() {
+        return Err(());
+    }
+    let mut step = [0; D];
+    for c in letters.chars() {
+        let (axis, sign) = AXIS_LETTERS
+            .iter()
+            .enumerate()
+            .find_map(|(axis, (neg, pos))| match c {
+                c if c == *neg => Some((axis, -1)),
+                c if c == *pos => Some((axis, 1)),
+                _ => None,
+            })
+            .ok_or(())?;
+        if axis >= D || step[axis] != 0 {
+            return Err(());
+        }
+        step[axis] = sign;
+    }
+    Ok(Idx(step))
+}
+
+// vertical first, so diagonals read like "UL"
+fn step_letters<const D: usize>(step: Idx<D>) -> String {
+    let mut letters = String::new();
+    for axis in [1, 0, 2] {
+        if axis >= D {
+            continue;
+        }
+        let (neg, pos) = AXIS_LETTERS[axis];
+        match step.0[axis].signum() {
+            -1 => letters.push(neg),
+            1 => letters.push(pos),
+            _ => (),
+        }
     }
+    letters
 }
 
-impl FromStr for Motion {
+impl<const D: usize> FromStr for Motion<D> {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<_> = s.split(' ').collect();
-        assert_eq!(parts.len(), 2);
-        let dir = Direction::from_str(parts[0])?;
-        let steps = parts[1].parse().map_err(|_| ())?;
-        Ok(Self::new(dir, steps))
+        let (letters, steps) = s.split_once(' ').ok_or(())?;
+        let step = parse_step(letters)?;
+        let steps = steps.parse().map_err(|_| ())?;
+        Ok(Self::from_step(step, steps))
     }
 }
 
-impl Display for Motion {
+impl<const D: usize> Display for Motion<D> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.direction, self.steps)
+        write!(f, "{} {}", step_letters(self.step), self.steps)
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Direction {
     Up,
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
 impl Direction {
-    fn step_diff(&self) -> (isize, isize) {
-        match self {
+    pub const ALL: [Direction; 8] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
+
+    fn step_diff(&self) -> Idx {
+        let (column, row) = match self {
             Direction::Up => (0, -1),
             Direction::Down => (0, 1),
             Direction::Left => (-1, 0),
             Direction::Right => (1, 0),
-        }
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        };
+        Idx::new(column, row)
     }
 }
 
+// the same letters as a `Motion`
 impl FromStr for Direction {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s {
-            "U" => Self::Up,
-            "D" => Self::Down,
-            "L" => Self::Left,
-            "R" => Self::Right,
-            e => return Err(()),
-        })
+        let step = parse_step(s)?;
+        Self::ALL
+            .into_iter()
+            .find(|d| d.step_diff() == step)
+            .ok_or(())
     }
 }
 
 impl Display for Direction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Direction::Up => "U",
-                Direction::Down => "D",
-                Direction::Left => "L",
-                Direction::Right => "R",
-            }
-        )
+        write!(f, "{}", step_letters(self.step_diff()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn motions(s: &str) -> Vec<Motion> {
+        s.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    const EXAMPLE: &str = "R 4
+U 4
+L 3
+D 1
+R 4
+D 1
+L 5
+R 2";
+
+    #[test]
+    fn tail_visits_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        assert_eq!(rope_sim.tail_visits(&motions(EXAMPLE)), 13);
+
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 10]);
+        assert_eq!(rope_sim.tail_visits(&motions(EXAMPLE)), 1);
+    }
+
+    #[test]
+    fn run_report_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 10]);
+        let report = rope_sim.run(&motions(EXAMPLE));
+
+        assert_eq!(report.knot_count(), 10);
+        assert_eq!(report.tail_visits(), 1);
+        assert_eq!(report.visited(1).len(), 13);
+        assert_eq!(report.distance_moved(0), 24);
+        assert_eq!(report.final_positions()[0], Idx::new(2, -2));
+        assert_eq!(report.final_positions(), rope_sim.positions());
+
+        let bounds = report.bounds();
+        assert_eq!(bounds.min(), Idx::new(0, -4));
+        assert_eq!(bounds.max(), Idx::new(5, 0));
+    }
+
+    #[test]
+    fn diagonal_motions_test() {
+        let motions = motions("UR 2\nDL 1");
+        assert_eq!(motions[0].to_string(), "UR 2");
+
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        let report = rope_sim.run(&motions);
+        assert_eq!(
+            report.final_positions(),
+            &[Idx::new(1, -1), Idx::new(1, -1)]
+        );
+        assert_eq!(report.distance_moved(0), 3);
+
+        // directions and motions read the same letters, in either order
+        for letters in ["UR", "RU", "LD", "U"] {
+            let direction: Direction = letters.parse().unwrap();
+            let motion: Motion = format!("{} 1", letters).parse().unwrap();
+            assert_eq!(Motion::new(direction, 1).to_string(), motion.to_string());
+        }
+        assert_eq!("RU".parse(), Ok(Direction::UpRight));
+        assert_eq!(Direction::DownLeft.to_string(), "DL");
+        assert!("UD".parse::<Direction>().is_err());
+        assert!("F".parse::<Direction>().is_err());
+        assert!("".parse::<Direction>().is_err());
+    }
+
+    #[test]
+    fn move_towards_large_gap_test() {
+        let tail = Idx::new(0, 0);
+
+        assert_eq!(tail.move_towards(Idx::new(5, 2)), Idx::new(1, 1));
+        assert_eq!(tail.move_towards(Idx::new(-7, 0)), Idx::new(-1, 0));
+    }
+
+    #[test]
+    fn teleporting_head_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 3]);
+        rope_sim.run(&[Motion::from_step(Idx::new(4, 0), 1)]);
+
+        assert_eq!(
+            rope_sim.positions(),
+            &[Idx::new(4, 0), Idx::new(1, 0), Idx::new(0, 0)]
+        );
+    }
+
+    #[test]
+    fn three_dimensional_test() {
+        let motions: Vec<Motion<3>> = ["B 3", "UR 2", "F 1"]
+            .iter()
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let mut rope_sim = RopeSim::new(vec![Idx::new_3d(0, 0, 0); 2]);
+        let report = rope_sim.run(&motions);
+
+        assert_eq!(report.final_positions()[0], Idx::new_3d(2, -2, 2));
+        assert_eq!(report.final_positions()[1], Idx::new_3d(1, -1, 3));
+        assert_eq!(report.bounds().extent(2), 4);
+        assert!("F 1".parse::<Motion>().is_err());
+    }
+
+    #[test]
+    fn render_image_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        let mut recorder = FrameRecorder::default();
+        rope_sim.run_with(&motions("R 2"), &mut recorder);
+
+        let image = recorder.render_image(2).unwrap();
+        assert_eq!(image.dimensions(), (3 * CELL_SIZE, CELL_SIZE));
+        // the tail has passed through the first cell, the head is drawn with a black "0"
+        assert_eq!(image[(0, 0)], TRAIL);
+        assert_eq!(image[(2 * CELL_SIZE, 0)], Rgb::RED);
+        assert_eq!(image[(2 * CELL_SIZE + 2, 1)], Rgb::BLACK);
+        assert_eq!(image[(CELL_SIZE - 1, 0)], BACKGROUND);
+        assert_eq!(recorder.render_image(3), None);
+
+        // a recorder that never watched a run has nothing to draw
+        let empty = FrameRecorder::default();
+        assert_eq!(empty.render(0), None);
+        assert_eq!(empty.render_image(0), None);
+        let dir = std::env::temp_dir().join(format!("aoc-2022-rope-empty-{}", std::process::id()));
+        let error = empty.write_ppm_frames(&dir, 1).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn write_ppm_frames_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        let mut recorder = FrameRecorder::default();
+        rope_sim.run_with(&motions(EXAMPLE), &mut recorder);
+
+        let dir = std::env::temp_dir().join(format!("aoc-2022-rope-{}", std::process::id()));
+        let written = recorder.write_ppm_frames(&dir, 10);
+        let files = fs::read_dir(&dir).map(|d| d.count());
+        fs::remove_dir_all(&dir).unwrap();
+
+        // frames 0, 10, 20 and the last one (24)
+        assert_eq!(written.unwrap(), 4);
+        assert_eq!(files.unwrap(), 4);
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn write_gif_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        let mut recorder = FrameRecorder::default();
+        rope_sim.run_with(&motions("R 2\nU 1"), &mut recorder);
+
+        let mut out = Vec::new();
+        recorder.write_gif(&mut out, 1, 10).unwrap();
+        assert_eq!(&out[..6], b"GIF89a");
+
+        let error = FrameRecorder::default().write_gif(Vec::new(), 1, 10);
+        assert_eq!(error.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn closure_observer_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 2]);
+        let mut steps = 0;
+        rope_sim.run_with(&motions(EXAMPLE), &mut |_head_move, _knots: &[Idx]| {
+            steps += 1
+        });
+
+        assert_eq!(steps, 24);
+    }
+
+    #[test]
+    fn frame_recorder_test() {
+        let mut rope_sim = RopeSim::new(vec![Idx::new(0, 0); 3]);
+        let mut recorder = FrameRecorder::default();
+        rope_sim.run_with(&motions("R 3\nU 1"), &mut recorder);
+
+        assert_eq!(recorder.frames().len(), 5);
+        let bounds = recorder.bounds().unwrap();
+        assert_eq!((bounds.width(), bounds.height()), (4, 2));
+        assert_eq!(recorder.render(4).unwrap(), "...0\n.21.\n");
     }
 }
@@ -1,5 +1,6 @@
+use crate::heatmap::Rgb;
 use simple_grid::{Grid, GridIndex};
-use std::{collections::HashSet, fmt::Display};
+use std::{cmp::Reverse, fmt::Display};
 
 pub struct Trees {
     grid: Grid<Tree>,
@@ -10,10 +11,17 @@ impl Trees {
         Self { grid }
     }
 
-    pub fn best_scenic_score(&self) -> u32 {
-        let mut best = None;
-        for idx in self.grid.indices() {
-            let score = self.scenic_score(idx.column(), idx.row());
+    pub fn best_scenic_score(&self) -> u64 {
+        let (_best_idx, best_score) = self.best_scenic_tree().unwrap();
+        best_score
+    }
+
+    // ties go to the first tree in reading order
+    pub fn best_scenic_tree(&self) -> Option<(GridIndex, u64)> {
+        let scores = self.scenic_score_grid();
+        let mut best: Option<(GridIndex, u64)> = None;
+        for idx in scores.indices() {
+            let score = scores[idx];
             if best
                 .map(|(_idx, best_score)| score > best_score)
                 .unwrap_or(true)
@@ -21,124 +29,309 @@ impl Trees {
                 best = Some((idx, score));
             }
         }
-
-        let (best_idx, best_score) = best.unwrap();
-
-        best_score
+        best
     }
 
-    fn scenic_score(&self, tree_column: usize, tree_row: usize) -> u32 {
-        let tree_height = self.grid[(tree_column, tree_row)].height();
+    pub fn analyze(&self) -> ForestAnalysis {
+        let distances = Direction::ALL.map(|direction| self.viewing_distances(direction));
+        let visible = Direction::ALL.map(|direction| self.visible_from(direction));
 
-        // look up
-        let mut up_score = 0;
-        for row in (0..tree_row).rev() {
-            let idx = GridIndex::new(tree_column, row);
-            let height_at = self.grid[idx].height();
-            up_score += 1;
-            if height_at >= tree_height {
-                break;
-            }
-        }
+        let reports = self
+            .grid
+            .indices()
+            .map(|idx| {
+                let [up, down, left, right] = distances.each_ref().map(|d| d[idx]);
+                TreeReport {
+                    idx,
+                    height: self.grid[idx].height(),
+                    viewing_distances: ViewingDistances {
+                        up,
+                        down,
+                        left,
+                        right,
+                    },
+                    visible_from: Direction::ALL
+                        .into_iter()
+                        .zip(visible.iter())
+                        .filter(|(_, visible)| visible[idx])
+                        .map(|(direction, _)| direction)
+                        .collect(),
+                }
+            })
+            .collect();
 
-        // look down
-        let mut down_score = 0;
-        for row in (tree_row + 1..self.grid.height()) {
-            let idx = GridIndex::new(tree_column, row);
-            let height_at = self.grid[idx].height();
-            down_score += 1;
-            if height_at >= tree_height {
-                break;
-            }
+        ForestAnalysis {
+            width: self.grid.width(),
+            reports,
         }
+    }
+
+    pub fn count_visible(&self) -> usize {
+        self.visibility_grid().cell_iter().filter(|v| **v).count()
+    }
 
-        // look left
-        let mut left_score = 0;
-        for column in (0..tree_column).rev() {
-            let idx = GridIndex::new(column, tree_row);
-            let height_at = self.grid[idx].height();
-            left_score += 1;
-            if height_at >= tree_height {
-                break;
+    // products of four distances, which outgrow a u32 in large forests
+    pub fn scenic_score_grid(&self) -> Grid<u64> {
+        let mut scores = Grid::new(
+            self.grid.width(),
+            self.grid.height(),
+            vec![1; self.grid.area()],
+        );
+        for direction in Direction::ALL {
+            let distances = self.viewing_distances(direction);
+            for idx in self.grid.indices() {
+                scores[idx] *= distances[idx] as u64;
             }
         }
+        scores
+    }
 
-        // look right
-        let mut right_score = 0;
-        for column in (tree_column + 1..self.grid.width()) {
-            let idx = GridIndex::new(column, tree_row);
-            let height_at = self.grid[idx].height();
-            right_score += 1;
-            if height_at >= tree_height {
-                break;
+    pub fn visibility_grid(&self) -> Grid<bool> {
+        let mut visible = Grid::new_default(self.grid.width(), self.grid.height());
+        for direction in Direction::ALL {
+            let visible_from = self.visible_from(direction);
+            for idx in self.grid.indices() {
+                visible[idx] |= visible_from[idx];
             }
         }
-
-        up_score * down_score * left_score * right_score
+        visible
     }
 
-    pub fn count_visible(&self) -> usize {
-        let mut visible = HashSet::new();
-
-        for row in self.grid.rows() {
-            // left to right
-            let mut tallest_so_far = None;
-            for column in self.grid.columns() {
-                let idx = GridIndex::new(column, row);
-                let tree = &self.grid[idx];
-                if tallest_so_far
-                    .map(|tallest| tree.height() > tallest)
-                    .unwrap_or(true)
-                {
-                    visible.insert(idx);
-                    tallest_so_far = Some(tree.height());
-                }
-            }
-            // right to left
-            let mut tallest_so_far = None;
-            for column in self.grid.columns().rev() {
-                let idx = GridIndex::new(column, row);
-                let tree = &self.grid[idx];
-                if tallest_so_far
-                    .map(|tallest| tree.height() > tallest)
-                    .unwrap_or(true)
+    /// How many trees each tree can see when looking in `direction`.
+    ///
+    /// Every line is swept once from the edge in `direction`, keeping a stack of the trees seen so far
+    /// with non-increasing heights, so the top of the stack is always the tree that blocks the view.
+    pub fn viewing_distances(&self, direction: Direction) -> Grid<u32> {
+        let mut distances = Grid::new_default(self.grid.width(), self.grid.height());
+        for line in self.lines_from(direction) {
+            let mut stack: Vec<(usize, u32)> = Vec::new();
+            for (pos, idx) in line.into_iter().enumerate() {
+                let height = self.grid[idx].height();
+                while stack
+                    .last()
+                    .map(|(_, blocking)| *blocking < height)
+                    .unwrap_or(false)
                 {
-                    visible.insert(idx);
-                    tallest_so_far = Some(tree.height());
+                    stack.pop();
                 }
+                let distance = match stack.last() {
+                    Some((blocking_pos, _)) => pos - blocking_pos,
+                    None => pos,
+                };
+                distances[idx] = distance as u32;
+                stack.push((pos, height));
             }
         }
+        distances
+    }
 
-        for column in self.grid.columns() {
-            // up to down
+    /// Which trees can be seen from outside the grid, looking in from the edge in `direction`.
+    pub fn visible_from(&self, direction: Direction) -> Grid<bool> {
+        let mut visible = Grid::new_default(self.grid.width(), self.grid.height());
+        for line in self.lines_from(direction) {
             let mut tallest_so_far = None;
-            for row in self.grid.rows() {
-                let idx = GridIndex::new(column, row);
-                let tree = &self.grid[idx];
+            for idx in line {
+                let height = self.grid[idx].height();
                 if tallest_so_far
-                    .map(|tallest| tree.height() > tallest)
+                    .map(|tallest| height > tallest)
                     .unwrap_or(true)
                 {
-                    visible.insert(idx);
-                    tallest_so_far = Some(tree.height());
+                    visible[idx] = true;
+                    tallest_so_far = Some(height);
                 }
             }
-            // down to up
-            let mut tallest_so_far = None;
-            for row in self.grid.rows().rev() {
-                let idx = GridIndex::new(column, row);
-                let tree = &self.grid[idx];
-                if tallest_so_far
-                    .map(|tallest| tree.height() > tallest)
-                    .unwrap_or(true)
-                {
-                    visible.insert(idx);
-                    tallest_so_far = Some(tree.height());
-                }
+        }
+        visible
+    }
+
+    pub fn height_heatmap(&self) -> Grid<Rgb> {
+        let max = self.grid.cell_iter().map(|t| t.height()).max().unwrap_or(0);
+        self.map_grid(|idx| Rgb::gradient(self.grid[idx].height(), max))
+    }
+
+    pub fn visibility_heatmap(&self) -> Grid<Rgb> {
+        let visible = self.visibility_grid();
+        self.map_grid(|idx| match visible[idx] {
+            true => Rgb::GREEN,
+            false => Rgb::BLACK,
+        })
+    }
+
+    // the tree picked by `best_scenic_tree` is drawn in red
+    pub fn scenic_score_heatmap(&self) -> Grid<Rgb> {
+        let scores = self.scenic_score_grid();
+        let max = scores.cell_iter().copied().max().unwrap_or(0);
+        // scaled down only as far as the gradient's u32 range needs
+        let scale = max.div_ceil(u32::MAX as u64).max(1);
+        let best = self.best_scenic_tree().map(|(idx, _)| idx);
+        self.map_grid(|idx| {
+            if Some(idx) == best {
+                Rgb::RED
+            } else {
+                Rgb::gradient((scores[idx] / scale) as u32, (max / scale) as u32)
             }
+        })
+    }
+
+    fn map_grid<T, F: Fn(GridIndex) -> T>(&self, f: F) -> Grid<T> {
+        Grid::new(
+            self.grid.width(),
+            self.grid.height(),
+            self.grid.indices().map(f).collect(),
+        )
+    }
+
+    // every row or column, ordered starting at the edge in `direction`
+    fn lines_from(&self, direction: Direction) -> Vec<Vec<GridIndex>> {
+        match direction {
+            Direction::Up => self
+                .grid
+                .columns()
+                .map(|column| {
+                    self.grid
+                        .rows()
+                        .map(|row| GridIndex::new(column, row))
+                        .collect()
+                })
+                .collect(),
+            Direction::Down => self
+                .grid
+                .columns()
+                .map(|column| {
+                    self.grid
+                        .rows()
+                        .rev()
+                        .map(|row| GridIndex::new(column, row))
+                        .collect()
+                })
+                .collect(),
+            Direction::Left => self
+                .grid
+                .rows()
+                .map(|row| {
+                    self.grid
+                        .columns()
+                        .map(|column| GridIndex::new(column, row))
+                        .collect()
+                })
+                .collect(),
+            Direction::Right => self
+                .grid
+                .rows()
+                .map(|row| {
+                    self.grid
+                        .columns()
+                        .rev()
+                        .map(|column| GridIndex::new(column, row))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+pub struct ForestAnalysis {
+    width: usize,
+    reports: Vec<TreeReport>,
+}
+
+impl ForestAnalysis {
+    pub fn tree(&self, idx: GridIndex) -> &TreeReport {
+        &self.reports[idx.row() * self.width + idx.column()]
+    }
+
+    pub fn trees(&self) -> &[TreeReport] {
+        &self.reports
+    }
+
+    pub fn best(&self) -> Option<&TreeReport> {
+        self.top(1).into_iter().next()
+    }
+
+    // highest score first, ties broken by reading order (row, then column)
+    pub fn top(&self, k: usize) -> Vec<&TreeReport> {
+        let mut sorted: Vec<_> = self.reports.iter().collect();
+        sorted.sort_by_key(|r| (Reverse(r.scenic_score()), r.idx.row(), r.idx.column()));
+        sorted.truncate(k);
+        sorted
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeReport {
+    idx: GridIndex,
+    height: u32,
+    viewing_distances: ViewingDistances,
+    visible_from: Vec<Direction>,
+}
+
+impl TreeReport {
+    pub fn idx(&self) -> GridIndex {
+        self.idx
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn viewing_distances(&self) -> ViewingDistances {
+        self.viewing_distances
+    }
+
+    pub fn scenic_score(&self) -> u64 {
+        self.viewing_distances.scenic_score()
+    }
+
+    pub fn visible_from(&self) -> &[Direction] {
+        &self.visible_from
+    }
+
+    pub fn is_visible(&self) -> bool {
+        !self.visible_from.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewingDistances {
+    up: u32,
+    down: u32,
+    left: u32,
+    right: u32,
+}
+
+impl ViewingDistances {
+    pub fn get(&self, direction: Direction) -> u32 {
+        match direction {
+            Direction::Up => self.up,
+            Direction::Down => self.down,
+            Direction::Left => self.left,
+            Direction::Right => self.right,
         }
-        visible.len()
     }
+
+    pub fn scenic_score(&self) -> u64 {
+        [self.up, self.down, self.left, self.right]
+            .iter()
+            .map(|d| *d as u64)
+            .product()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
 }
 
 pub struct Tree(u32);
@@ -158,3 +351,103 @@ impl Display for Tree {
         write!(f, "{}", self.height())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Trees {
+        let lines = ["30373", "25512", "65332", "33549", "35390"];
+        let data = lines
+            .join("")
+            .chars()
+            .map(|c| Tree::new(c.to_digit(10).unwrap()))
+            .collect();
+        Trees::new(Grid::new(5, 5, data))
+    }
+
+    #[test]
+    fn count_visible_test() {
+        assert_eq!(example().count_visible(), 21);
+    }
+
+    #[test]
+    fn viewing_distances_test() {
+        let trees = example();
+        let idx = GridIndex::new(2, 3);
+
+        assert_eq!(trees.viewing_distances(Direction::Up)[idx], 2);
+        assert_eq!(trees.viewing_distances(Direction::Left)[idx], 2);
+        assert_eq!(trees.viewing_distances(Direction::Down)[idx], 1);
+        assert_eq!(trees.viewing_distances(Direction::Right)[idx], 2);
+    }
+
+    #[test]
+    fn scenic_score_grid_test() {
+        let trees = example();
+        let scores = trees.scenic_score_grid();
+
+        assert_eq!(scores[GridIndex::new(2, 1)], 4);
+        assert_eq!(scores[GridIndex::new(2, 3)], 8);
+        assert_eq!(scores[GridIndex::new(0, 0)], 0);
+        assert_eq!(trees.best_scenic_score(), 8);
+
+        // a tall tree in the middle of a flat forest sees 260 trees each way, 260^4 > u32::MAX
+        let size = 521;
+        let mut grid = Grid::new(size, size, (0..size * size).map(|_| Tree::new(0)).collect());
+        grid[(260, 260)] = Tree::new(9);
+        let trees = Trees::new(grid);
+        assert_eq!(
+            trees.best_scenic_tree(),
+            Some((GridIndex::new(260, 260), 260u64.pow(4)))
+        );
+        assert_eq!(
+            trees.analyze().best().unwrap().scenic_score(),
+            260u64.pow(4)
+        );
+    }
+
+    #[test]
+    fn heatmaps_test() {
+        let trees = example();
+
+        let visibility = trees.visibility_heatmap();
+        assert_eq!(visibility[GridIndex::new(0, 0)], Rgb::GREEN);
+        assert_eq!(visibility[GridIndex::new(2, 2)], Rgb::BLACK);
+
+        let heights = trees.height_heatmap();
+        assert_eq!(heights[GridIndex::new(4, 3)], Rgb::gradient(9, 9));
+
+        let scores = trees.scenic_score_heatmap();
+        assert_eq!(scores[GridIndex::new(2, 3)], Rgb::RED);
+        assert_eq!(scores[GridIndex::new(1, 2)], Rgb::gradient(6, 8));
+    }
+
+    #[test]
+    fn analyze_test() {
+        let analysis = example().analyze();
+
+        let best = analysis.best().unwrap();
+        assert_eq!(best.idx(), GridIndex::new(2, 3));
+        assert_eq!(best.height(), 5);
+        assert_eq!(best.scenic_score(), 8);
+        assert_eq!(best.viewing_distances().get(Direction::Down), 1);
+        assert_eq!(best.visible_from(), &[Direction::Down, Direction::Left]);
+
+        let middle = analysis.tree(GridIndex::new(2, 2));
+        assert!(!middle.is_visible());
+
+        let top: Vec<_> = analysis.top(2).iter().map(|r| r.idx()).collect();
+        assert_eq!(top, vec![GridIndex::new(2, 3), GridIndex::new(1, 2)]);
+
+        // edge trees all score 0, so they come last in reading order
+        let all = analysis.top(100);
+        assert_eq!(all.len(), 25);
+        assert_eq!(all[9].idx(), GridIndex::new(0, 0));
+        assert_eq!(all[24].idx(), GridIndex::new(4, 4));
+        assert_eq!(
+            example().best_scenic_tree(),
+            Some((GridIndex::new(2, 3), 8))
+        );
+    }
+}